@@ -1,11 +1,15 @@
-use std::{collections::{HashMap, hash_map::{Entry}}, path::{PathBuf}};
+use std::{collections::{HashMap, hash_map::{Entry}}, path::{PathBuf}, cmp::max};
 
 use rayon::iter::{ParallelIterator, IntoParallelRefIterator};
 use solana_program::pubkey::Pubkey;
 use solana_sdk::transaction::Transaction;
 use solana_transaction_status::{EncodedConfirmedBlock, EncodedTransactionWithStatusMeta};
 
-use crate::{files::{write_pubkey_counts, load_blocks_chunk_json}, util::{time_run, log_err}};
+use crate::{
+    files::write_pubkey_counts, normalized::{NormalizedBlock, VoteFilter},
+    slot_range::SlotRange, chunk_stream::{stream_normalized_chunk_in_range, filter_votes},
+    map_reduce::{ChunkMapReduce, BlockIter}, util::time_run
+};
 
 
 pub(crate) type PubkeyTxCount = (Pubkey, u32); 
@@ -16,10 +20,11 @@ pub(crate) struct CountedTxs<'a> {
     pub data: &'a Vec<PubkeyTxCount>
 }
 
-pub fn process_block_stream(block_files: &[PathBuf]) {
+pub(crate) fn process_block_stream(block_files: &[PathBuf], range: &SlotRange, threads: Option<usize>, votes: VoteFilter) {
     println!("testing chunked stream processing...");
+    if votes != VoteFilter::Include { println!("vote transactions:  {}", votes.name()) }
 
-    let acct_set = find_account_set_stream(block_files);
+    let acct_set = find_account_set_stream(block_files, range, threads, votes);
 
     println!("done processing, converting to vec & sorting...");
     let mut accts_vec:Vec<(Pubkey, u32)> = acct_set.iter().map(|e| (*e.0, *e.1)).collect();
@@ -31,7 +36,7 @@ pub fn process_block_stream(block_files: &[PathBuf]) {
         
     println!("\nunique public keys counted: {}\n", acct_set.len());
 
-    accts_vec[(accts_vec.len() - 15)..].iter().for_each(|t| {
+    accts_vec[accts_vec.len().saturating_sub(15)..].iter().for_each(|t| {
         println!("public key: {} - entries:  {}", t.0, t.1);
     });
 }
@@ -46,35 +51,6 @@ pub fn decode_txs_map(e_txs: &Vec<EncodedTransactionWithStatusMeta>) -> Vec<Tran
     }).collect::<Vec<Transaction>>()
 }
 
-/* 
-    given a set of paths pointing to data parsable as 'T':
-    * load all files & parse to type 'T'
-    * run 'each_chunk' on data parsed from each file
-    * reduce all the chunks' output to one single value of the same type, 'C'
-*/
-pub fn map_reduce_chunk_files<U: Sized + Send, T: Send, C: Send>(
-    paths: &[PathBuf], 
-    load_chunk_file: fn(&PathBuf) -> Option<Vec<(U, T)>>, 
-    each_chunk: fn(&[(U, T)]) -> C, 
-    reduce: fn(Vec<C>) -> C) 
-    -> C
-{
-    // in parallel, load & parse each chunk, before running supplied fn on the data
-    let sub_results: Vec<C> = paths.par_iter()
-    .filter_map(|path| {
-        match load_chunk_file(path) {
-            Some(c) => Some(each_chunk(&c)),
-            None => {
-                log_err(&format!("chunk load failed, path:  {:?}", path));
-                None 
-            }
-        }
-    }).collect();
-
-    // take all the sub-results, aggregate them into one result of same type
-    reduce(sub_results)
-}
-
 fn add_or_increment<T: Copy + Eq + std::hash::Hash>(key: T, hm: &mut HashMap<T, u32>) {
     match hm.entry(key) {
         Entry::Occupied(mut tx_count) => {
@@ -85,7 +61,8 @@ fn add_or_increment<T: Copy + Eq + std::hash::Hash>(key: T, hm: &mut HashMap<T,
 }
 
 fn chunks_count<'a, T>(data: &'a Vec<T>, chunk_count: usize) -> Vec<&'a [T]> {
-    let chunk_len = data.len() / chunk_count;
+    // never 0, or chunks() panics when there's less data than chunks
+    let chunk_len = max(data.len() / chunk_count, 1);
     let result: Vec<&[T]> = data.chunks(chunk_len).collect();
     result.to_owned()
 }
@@ -122,20 +99,24 @@ fn reduce_count_chunk(chunk: &[PubkeyTxCountMap]) -> PubkeyTxCountMap {
 
 // given a set of .json file paths containing Solana block info,
 // process them all in a streaming manner and collect the results
-pub fn find_account_set_stream(block_files: &[PathBuf]) -> PubkeyTxCountMap {
+pub(crate) fn find_account_set_stream(block_files: &[PathBuf], range: &SlotRange, threads: Option<usize>,
+    votes: VoteFilter) -> PubkeyTxCountMap
+{
     let result = time_run(|| {
-        map_reduce_chunk_files::<u64, EncodedConfirmedBlock, PubkeyTxCountMap>(
+        ChunkMapReduce::new().threads(threads).progress(true).run(
             block_files,
-            |p| { load_blocks_chunk_json(p) },  
-            find_account_set_tuple,                         // in each chunk, count seen public keys
-            reduce_count_map)                           // aggregate occurence counts into one map
+            // blocks outside the range are dropped, as are transactions the vote filter doesn't keep
+            |p| stream_normalized_chunk_in_range(p, range).map(|s| filter_votes(s, votes)),
+            find_account_set_normalized,                        // in each chunk, count seen public keys
+            reduce_count_map)                                   // aggregate occurence counts into one map
     });
 
     let bf_len = block_files.len();
     let seconds = result.time.as_secs_f32();
+    result.data.print_summary();
     println!("total time to process {} chunks:  {:3} seconds\n", bf_len, seconds);
 
-    result.data
+    result.data.result
 }
 
 pub fn find_account_set(blocks: &[EncodedConfirmedBlock]) -> PubkeyTxCountMap {
@@ -153,15 +134,14 @@ pub fn find_account_set(blocks: &[EncodedConfirmedBlock]) -> PubkeyTxCountMap {
     hash_map
 }
 
-pub fn find_account_set_tuple(blocks: &[(u64, EncodedConfirmedBlock)]) -> PubkeyTxCountMap {
+// same as find_account_set(), but the transactions are already decoded
+pub(crate) fn find_account_set_normalized(blocks: BlockIter<NormalizedBlock>) -> PubkeyTxCountMap {
     let mut hash_map = PubkeyTxCountMap::new();
 
-    blocks.iter().for_each(|data| {
-        let ecb = &(data.1);
-        let txs = decode_txs_map(&ecb.transactions);
-        for tx in txs {
-            for acct in &tx.message.account_keys {
-                add_or_increment(*acct, &mut hash_map);
+    blocks.for_each(|block| {
+        for tx in &block.transactions {
+            for acct in &tx.accounts {
+                add_or_increment(acct.pubkey, &mut hash_map);
             }
         };
     });
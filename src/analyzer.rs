@@ -0,0 +1,290 @@
+use std::{any::Any, cmp::Ordering, collections::BTreeMap, fs, path::PathBuf, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{
+    cli::CliArguments,
+    normalized::{NormalizedBlock, NormalizedTx, VoteFilter},
+    files::CHUNKED_BLOCKS_DIR,
+    chunk_stream::{stream_normalized_chunk_in_range, filter_votes},
+    manifest::require_chunk_order,
+    map_reduce::{BlockIter, ChunkMapReduce},
+    slot_range::{SlotRange, select_chunk_paths},
+    key_counts::KeyCounts, program_activity::ProgramActivity, fees::FeeAnalysis, tx_errors::ErrorAnalysis,
+    sol_flow::{SolFlow, parse_watch_list}, token_flow::TokenFlow,
+    anchor_idl::{load_idl_args, IDL_DIR}, anchor_activity::AnchorActivity, votes::VoteAnalysis, cpi_graph::CpiGraph,
+    util::{log_err, time_run, PATH_SEP, do_or_die}
+};
+
+
+/*
+    an analysis that can run over chunked blocks alongside any others, in the same pass:
+    * each chunk gets its own empty() copy, which sees every block in that chunk
+    * the chunks' copies are merged, in slot order, into one
+    * that one is finalized, printed as a report & written to the output dir
+    parameters (e.g. how many top entries to keep) are set when the analyzer is built from the CLI args,
+    & carried into every empty() copy
+*/
+pub(crate) trait Analyzer: Send + Sync + Sized + 'static {
+    // a copy with the same parameters & no data yet
+    fn empty(&self) -> Self;
+
+    fn block(&mut self, block: &NormalizedBlock) {
+        block.transactions.iter().for_each(|tx| self.tx(block, tx));
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, _tx: &NormalizedTx) {}
+
+    fn merge(&mut self, other: Self);
+
+    // anything that can only be worked out once all the data is in, like sorting or percentiles
+    fn finalize(&mut self) {}
+
+    fn print_report(&self);
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String>;
+}
+
+// an Analyzer of any type, so different analyzers can be held & run together
+pub(crate) trait DynAnalyzer: Send + Sync {
+    fn empty_dyn(&self) -> Box<dyn DynAnalyzer>;
+    fn block(&mut self, block: &NormalizedBlock);
+    fn merge_dyn(&mut self, other: Box<dyn DynAnalyzer>);
+    fn finalize(&mut self);
+    fn print_report(&self);
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String>;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<A: Analyzer> DynAnalyzer for A {
+    fn empty_dyn(&self) -> Box<dyn DynAnalyzer> { Box::new(self.empty()) }
+    fn block(&mut self, block: &NormalizedBlock) { Analyzer::block(self, block) }
+    fn finalize(&mut self) { Analyzer::finalize(self) }
+    fn print_report(&self) { Analyzer::print_report(self) }
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> { Analyzer::write_output(self, out) }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> { self }
+
+    // copies are only ever merged into the analyzer they were made from, so the types always match
+    fn merge_dyn(&mut self, other: Box<dyn DynAnalyzer>) {
+        match other.into_any().downcast::<A>() {
+            Ok(other) => self.merge(*other),
+            Err(_) => log_err("can't merge analyzers of different types"),
+        }
+    }
+}
+
+// a registered analyzer, runnable by name with 'sol-data analyze <name>'
+pub(crate) struct AnalyzerInfo {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub build: fn(&CliArguments) -> Result<Box<dyn DynAnalyzer>, String>,
+}
+
+pub(crate) static ANALYZERS: [AnalyzerInfo; 9] = [
+    AnalyzerInfo {
+        name: "key_counts",
+        about: "transactions each public key appears in, as fee payer, signer, writable or readonly",
+        build: |args| Ok(Box::new(KeyCounts::new(top_arg(args), args.role))),
+    },
+    AnalyzerInfo {
+        name: "program_activity",
+        about: "transactions, instructions, CPI calls, failures & signers for each invoked program",
+        build: |args| Ok(Box::new(ProgramActivity::new(top_arg(args)))),
+    },
+    AnalyzerInfo {
+        name: "fees",
+        about: "fees paid, per block, per signature, by fee payer & by invoked program",
+        build: |args| Ok(Box::new(FeeAnalysis::new(top_arg(args)))),
+    },
+    AnalyzerInfo {
+        name: "errors",
+        about: "failed transactions by error kind, failing program, custom error code & slot window",
+        build: |args| Ok(Box::new(ErrorAnalysis::new(top_arg(args), slot_window_arg(args)))),
+    },
+    AnalyzerInfo {
+        name: "sol_flow",
+        about: "net SOL change per account from pre & post balances, top gainers & losers, lamports moved per slot",
+        build: |args| {
+            let watch = args.watch.as_deref().map(parse_watch_list).transpose()?;
+            Ok(Box::new(SolFlow::new(top_arg(args), args.exclude_fees, watch)))
+        },
+    },
+    AnalyzerInfo {
+        name: "token_flow",
+        about: "SPL token volume, holders & top senders/receivers per mint, & each owner's net change",
+        build: |args| Ok(Box::new(TokenFlow::new(top_arg(args)))),
+    },
+    AnalyzerInfo {
+        name: "anchor",
+        about: "instructions & events of programs with an Anchor IDL, by name, with their arg & field values",
+        build: |args| {
+            let registry = load_idl_args(args)?;
+            if registry.is_empty() { return Err(format!("no IDLs, use --idl or put them in {}", IDL_DIR)) }
+            Ok(Box::new(AnchorActivity::new(top_arg(args), Arc::new(registry))))
+        },
+    },
+    AnalyzerInfo {
+        name: "votes",
+        about: "vote & non-vote transactions, votes per validator, vote latency & missing voters per slot window",
+        build: |args| {
+            if args.votes == VoteFilter::Exclude { return Err("needs vote transactions, can't run with --votes exclude".to_string()) }
+            Ok(Box::new(VoteAnalysis::new(top_arg(args), slot_window_arg(args))))
+        },
+    },
+    AnalyzerInfo {
+        name: "cpi_graph",
+        about: "which programs call which by CPI, with call counts & depths, as a graph in DOT & GraphML",
+        build: |args| Ok(Box::new(CpiGraph::new(top_arg(args)))),
+    },
+];
+
+pub(crate) fn find_analyzer(name: &str) -> Option<&'static AnalyzerInfo> {
+    ANALYZERS.iter().find(|a| a.name == name)
+}
+
+// how many entries analyzers keep in their top-N lists, unless --top says otherwise
+pub(crate) const DEFAULT_TOP: usize = 20;
+
+pub(crate) fn top_arg(args: &CliArguments) -> usize {
+    args.top.unwrap_or(DEFAULT_TOP)
+}
+
+// how many slots analyzers group together in their per-window series, unless --slot-window says otherwise
+pub(crate) const DEFAULT_SLOT_WINDOW: u64 = 1000;
+
+pub(crate) fn slot_window_arg(args: &CliArguments) -> u64 {
+    args.slot_window.unwrap_or(DEFAULT_SLOT_WINDOW)
+}
+
+pub(crate) const PERCENTILES: [u8; 7] = [0, 10, 25, 50, 75, 90, 100];
+
+#[derive(Serialize)]
+pub(crate) struct Percentile {
+    pub percentile: u8,
+    pub value: u64,
+}
+
+// nearest-rank percentiles over counts of each value, so they're exact without keeping every value
+pub(crate) fn percentiles(counts: &BTreeMap<u64, u64>) -> Vec<Percentile> {
+    let total: u64 = counts.values().sum();
+    PERCENTILES.iter().map(|p| {
+        let rank = (*p as u64 * total).div_ceil(100).max(1);
+        let mut seen = 0;
+        let value = counts.iter().find(|(_, count)| { seen += *count; seen >= rank }).map(|(v, _)| *v).unwrap_or(0);
+        Percentile { percentile: *p, value }
+    }).collect()
+}
+
+// sort rows highest score first.  every ranked list breaks ties, e.g. by key, so reports & output files
+// come out the same every run, whatever order the chunks' hash maps were merged in
+pub(crate) fn ranked<T, S: Ord>(rows: &mut [T], score: impl Fn(&T) -> S, tie: impl Fn(&T, &T) -> Ordering) {
+    rows.sort_by(|a, b| score(b).cmp(&score(a)).then_with(|| tie(a, b)));
+}
+
+pub(crate) const ANALYSIS_DIR: &str = "blocks/analysis";
+
+// where one analyzer's output files go:  <dir>/<analyzer name><suffix>
+pub(crate) struct AnalysisOutput<'a> {
+    pub dir: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> AnalysisOutput<'a> {
+    pub fn path(&self, suffix: &str) -> String {
+        format!("{}{}{}{}", self.dir, PATH_SEP, self.name, suffix)
+    }
+
+    // <name><suffix>.json.  analyzers run with a filter (e.g. a role or a watch list) add a suffix for it,
+    // so a filtered run's output doesn't replace an unfiltered run's
+    pub fn write_json<T: Serialize>(&self, suffix: &str, data: &T) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(data).map_err(|e| e.to_string())?;
+        self.write_bytes(&format!("{}.json", suffix), &json)
+    }
+
+    pub fn write_text(&self, suffix: &str, text: &str) -> Result<(), String> {
+        self.write_bytes(suffix, text.as_bytes())
+    }
+
+    fn write_bytes(&self, suffix: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.path(suffix);
+        fs::write(&path, data).map_err(|e| format!("{}:  {}", path, e))?;
+        println!("{} written", path);
+        Ok(())
+    }
+}
+
+// run every analyzer over the chunks in one pass, each chunk's blocks streamed once.
+// analyzers only see the transactions the vote filter keeps.
+// returns the merged & finalized analyzers, in the order given
+pub(crate) fn run_analyzers(paths: &[PathBuf], analyzers: Vec<Box<dyn DynAnalyzer>>, range: &SlotRange,
+    threads: Option<usize>, votes: VoteFilter) -> Vec<Box<dyn DynAnalyzer>>
+{
+    let output = ChunkMapReduce::new().threads(threads).progress(true).run(
+        paths,
+        |p| stream_normalized_chunk_in_range(p, range).map(|s| filter_votes(s, votes)),
+        |blocks: BlockIter<NormalizedBlock>| {
+            let mut chunk_analyzers: Vec<Box<dyn DynAnalyzer>> = analyzers.iter().map(|a| a.empty_dyn()).collect();
+            blocks.for_each(|block| chunk_analyzers.iter_mut().for_each(|a| a.block(&block)));
+            chunk_analyzers
+        },
+        |chunk_results| {
+            let mut merged: Vec<Box<dyn DynAnalyzer>> = analyzers.iter().map(|a| a.empty_dyn()).collect();
+            chunk_results.into_iter().for_each(|chunk_analyzers| {
+                merged.iter_mut().zip(chunk_analyzers).for_each(|(m, a)| m.merge_dyn(a));
+            });
+            merged
+        });
+    output.print_summary();
+
+    let mut merged = output.result;
+    merged.iter_mut().for_each(|a| a.finalize());
+    merged
+}
+
+const NO_SRC_EXIT_MSG: &str = "can't analyze without a valid chunk directory!\nexiting\n";
+
+fn print_analyzer_list() {
+    println!("available analyzers:");
+    ANALYZERS.iter().for_each(|a| println!("    {:<24} {}", a.name, a.about));
+    println!();
+}
+
+// handler for the 'analyze' CLI task:  'sol-data analyze <name> [<name> ...]'
+pub(crate) fn analyze_cli(args: &CliArguments) {
+    if args.task_args.is_empty() { return print_analyzer_list() }
+
+    let mut names = Vec::<&str>::new();
+    let mut analyzers = Vec::<Box<dyn DynAnalyzer>>::new();
+    for name in args.task_args.iter() {
+        let info = match find_analyzer(name) {
+            Some(info) => info,
+            None => {
+                log_err(&format!("analyzer '{}' not recognized", name));
+                return print_analyzer_list();
+            }
+        };
+        if names.contains(&info.name) { continue }
+        match (info.build)(args) {
+            Ok(analyzer) => { names.push(info.name); analyzers.push(analyzer) },
+            Err(e) => return log_err(&format!("{}:  {}", name, e)),
+        }
+    }
+
+    let src_dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    do_or_die(|| fs::metadata(src_dir), NO_SRC_EXIT_MSG);
+    if !require_chunk_order(src_dir) { return }
+    let out_dir = args.dest.as_deref().unwrap_or(ANALYSIS_DIR);
+    if let Err(e) = fs::create_dir_all(out_dir) { return log_err(&e) }
+
+    println!("\nrunning analyzers:  {}", names.join(", "));
+    if args.votes != VoteFilter::Include { println!("vote transactions:  {}", args.votes.name()) }
+    let paths = select_chunk_paths(src_dir, &args.slot_range);
+    let timed = time_run(|| run_analyzers(&paths, analyzers, &args.slot_range, args.threads, args.votes));
+
+    names.iter().zip(timed.data.iter()).for_each(|(name, analyzer)| {
+        println!("\n--- {} ---", name);
+        analyzer.print_report();
+        if let Err(e) = analyzer.write_output(&AnalysisOutput { dir: out_dir, name }) { log_err(&e) }
+    });
+    println!("\ndone, time:  {:3} seconds", timed.time.as_secs_f32());
+}
@@ -0,0 +1,247 @@
+use std::{collections::{BTreeMap, HashMap}, sync::Arc};
+
+use serde::Serialize;
+use serde_json::Value;
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    anchor_idl::IdlRegistry,
+    normalized::{NormalizedBlock, NormalizedInstruction, NormalizedTx}
+};
+
+
+// Anchor programs' instructions & events, decoded with their registered IDLs & counted by name,
+// with what their args & fields were set to
+#[derive(Default)]
+pub(crate) struct AnchorActivity {
+    top: usize,
+    registry: Arc<IdlRegistry>,
+    instructions: HashMap<(Pubkey, String), NamedStats>,    // by program & instruction name
+    events: HashMap<(Pubkey, String), NamedStats>,          // by program & event name
+    undecoded: HashMap<Pubkey, u64>,                        // registered programs' data that didn't match their IDL
+    report: Option<AnchorReport>,                           // filled in by finalize()
+}
+
+#[derive(Default)]
+struct NamedStats {
+    count: u64,
+    failed: u64,                                // in transactions that failed
+    cpi: u64,                                   // invoked by another program, for instructions
+    values: BTreeMap<String, ValueStats>,       // by arg or field name
+}
+
+impl NamedStats {
+    fn add(&mut self, failed: bool, cpi: bool, values: &[(&str, Value)]) {
+        self.count += 1;
+        if failed { self.failed += 1 }
+        if cpi { self.cpi += 1 }
+        values.iter().for_each(|(name, value)| {
+            if let Some(stats) = ValueStats::of(value) { self.values.entry(name.to_string()).or_default().merge(stats) }
+        });
+    }
+
+    fn merge(&mut self, other: NamedStats) {
+        self.count += other.count;
+        self.failed += other.failed;
+        self.cpi += other.cpi;
+        other.values.into_iter().for_each(|(name, stats)| self.values.entry(name).or_default().merge(stats));
+    }
+}
+
+// numbers are summed up, anything else that's one value (bools, strings, pubkeys, enum variants) is counted by value.
+// arrays & structs are left out
+#[derive(Serialize, Clone, Default)]
+struct ValueStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    numeric: Option<NumericStats>,
+    #[serde(skip)]
+    counts: HashMap<String, u64>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct NumericStats {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl ValueStats {
+    fn of(value: &Value) -> Option<ValueStats> {
+        let counted = |v: String| Some(ValueStats { numeric: None, counts: HashMap::from([(v, 1)]) });
+        match value {
+            Value::Number(n) => n.as_f64().map(|n| ValueStats {
+                numeric: Some(NumericStats { count: 1, sum: n, min: n, max: n }),
+                counts: HashMap::new(),
+            }),
+            Value::String(s) => counted(s.clone()),
+            Value::Bool(b) => counted(b.to_string()),
+            Value::Null => counted("null".to_string()),
+            // an enum variant with fields, by its name
+            Value::Object(obj) if obj.len() == 1 => counted(obj.keys().next().cloned().unwrap_or_default()),
+            _ => None,
+        }
+    }
+
+    fn merge(&mut self, other: ValueStats) {
+        self.numeric = match (self.numeric, other.numeric) {
+            (Some(a), Some(b)) => Some(NumericStats {
+                count: a.count + b.count, sum: a.sum + b.sum, min: a.min.min(b.min), max: a.max.max(b.max)
+            }),
+            (a, b) => a.or(b),
+        };
+        other.counts.into_iter().for_each(|(value, count)| *self.counts.entry(value).or_insert(0) += count);
+    }
+
+    // most common first
+    fn top_values(&self, limit: usize) -> Vec<ValueCount> {
+        let mut sorted: Vec<(&String, &u64)> = self.counts.iter().collect();
+        ranked(&mut sorted, |(_, count)| **count, |a, b| a.0.cmp(b.0));
+        sorted.into_iter().take(limit).map(|(value, count)| ValueCount { value: value.clone(), count: *count }).collect()
+    }
+}
+
+#[derive(Serialize)]
+struct AnchorReport {
+    programs: usize,                            // with a registered IDL
+    instructions: Vec<NamedRow>,
+    events: Vec<NamedRow>,
+    undecoded: Vec<UndecodedRow>,
+}
+
+#[derive(Serialize)]
+struct NamedRow {
+    program: String,
+    program_name: String,
+    name: String,
+    count: u64,
+    failed: u64,
+    cpi: u64,
+    values: Vec<ValueRow>,
+}
+
+#[derive(Serialize)]
+struct ValueRow {
+    name: String,
+    #[serde(flatten)]
+    stats: ValueStats,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    top_values: Vec<ValueCount>,
+}
+
+#[derive(Serialize, Clone)]
+struct ValueCount {
+    value: String,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct UndecodedRow {
+    program: String,
+    count: u64,
+}
+
+impl AnchorActivity {
+    pub fn new(top: usize, registry: Arc<IdlRegistry>) -> AnchorActivity {
+        AnchorActivity { top, registry, ..Default::default() }
+    }
+
+    fn instruction(&mut self, ix: &NormalizedInstruction, failed: bool, cpi: bool) {
+        let registry = self.registry.clone();
+        match registry.decode_instruction(&ix.program_id, &ix.data) {
+            Ok(Some(decoded)) => self.instructions.entry((ix.program_id, decoded.name.to_string())).or_default()
+                .add(failed, cpi, &decoded.args),
+            Ok(None) => {},
+            Err(_) => *self.undecoded.entry(ix.program_id).or_insert(0) += 1,
+        }
+    }
+
+    fn rows(&self, stats: &HashMap<(Pubkey, String), NamedStats>) -> Vec<NamedRow> {
+        let mut rows: Vec<NamedRow> = stats.iter().map(|((program, name), s)| NamedRow {
+            program: program.to_string(),
+            program_name: self.registry.program_name(program).unwrap_or_default().to_string(),
+            name: name.clone(),
+            count: s.count,
+            failed: s.failed,
+            cpi: s.cpi,
+            values: s.values.iter().map(|(name, v)| ValueRow {
+                name: name.clone(), stats: v.clone(), top_values: v.top_values(self.top)
+            }).collect(),
+        }).collect();
+        ranked(&mut rows, |r| r.count, |a, b| a.program.cmp(&b.program).then(a.name.cmp(&b.name)));
+        rows
+    }
+}
+
+fn print_rows(title: &str, rows: &[NamedRow], top: usize) {
+    println!("\n    {:<48} {:>10} {:>10} {:>10}", title, "count", "failed", "cpi");
+    rows.iter().take(top).for_each(|row| {
+        println!("    {:<48} {:>10} {:>10} {:>10}", format!("{}::{}", row.program_name, row.name), row.count, row.failed, row.cpi);
+        row.values.iter().for_each(|v| {
+            let summary = match &v.stats.numeric {
+                Some(n) => format!("sum {},  min {},  max {},  mean {:.2}", n.sum, n.min, n.max, n.sum / n.count as f64),
+                None => v.top_values.iter().take(3).map(|c| format!("{} x{}", c.value, c.count)).collect::<Vec<_>>().join(",  "),
+            };
+            println!("        {:<24} {}", v.name, summary);
+        });
+    });
+}
+
+impl Analyzer for AnchorActivity {
+    fn empty(&self) -> AnchorActivity {
+        AnchorActivity::new(self.top, self.registry.clone())
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, tx: &NormalizedTx) {
+        let failed = tx.failed();
+        tx.instructions.iter().for_each(|ix| self.instruction(ix, failed, false));
+        tx.inner_instructions.iter().flat_map(|inner| inner.instructions.iter())
+            .for_each(|ix| self.instruction(ix, failed, true));
+
+        let registry = self.registry.clone();
+        // events that don't match their program's IDL are left out
+        registry.decode_events(tx).into_iter().flatten().for_each(|event| {
+            self.events.entry((event.program_id, event.name.to_string())).or_default().add(failed, false, &event.fields)
+        });
+    }
+
+    fn merge(&mut self, other: AnchorActivity) {
+        other.instructions.into_iter().for_each(|(key, stats)| self.instructions.entry(key).or_default().merge(stats));
+        other.events.into_iter().for_each(|(key, stats)| self.events.entry(key).or_default().merge(stats));
+        other.undecoded.into_iter().for_each(|(program, count)| *self.undecoded.entry(program).or_insert(0) += count);
+    }
+
+    fn finalize(&mut self) {
+        let mut undecoded: Vec<UndecodedRow> = self.undecoded.iter()
+            .map(|(program, count)| UndecodedRow { program: program.to_string(), count: *count })
+            .collect();
+        ranked(&mut undecoded, |u| u.count, |a, b| a.program.cmp(&b.program));
+
+        self.report = Some(AnchorReport {
+            programs: self.registry.len(),
+            instructions: self.rows(&self.instructions),
+            events: self.rows(&self.events),
+            undecoded,
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        println!("programs with an IDL:  {},  instructions decoded:  {},  events decoded:  {}", report.programs,
+                 report.instructions.iter().map(|r| r.count).sum::<u64>(), report.events.iter().map(|r| r.count).sum::<u64>());
+        print_rows("instruction", &report.instructions, self.top);
+        print_rows("event", &report.events, self.top);
+        report.undecoded.iter().for_each(|u| println!("\n{} instructions to {} didn't match its IDL", u.count, u.program));
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        match &self.report {
+            Some(report) => out.write_json("", report),
+            None => Ok(()),
+        }
+    }
+}
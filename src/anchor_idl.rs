@@ -0,0 +1,682 @@
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+use crate::{
+    cli::CliArguments,
+    files::dir_file_paths,
+    normalized::NormalizedTx,
+    util::snake_case
+};
+
+
+// where IDLs are loaded from when there's no --idl arg
+pub(crate) const IDL_DIR: &str = "blocks/idl";
+
+/*
+    Anchor programs' instructions & events, described by their IDL JSON files:
+    * an instruction's data starts with an 8 byte discriminator, sha256("global:<snake_case name>")[..8],
+      followed by its args, Borsh encoded
+    * an event is logged as "Program data: <base64>", where the data starts with sha256("event:<Name>")[..8]
+    newer IDLs list each discriminator explicitly, which is used instead when it's there
+*/
+#[derive(Default)]
+pub(crate) struct IdlRegistry {
+    programs: HashMap<Pubkey, ProgramIdl>,
+}
+
+struct ProgramIdl {
+    name: String,
+    instructions: HashMap<[u8; 8], InstructionDef>,
+    events: HashMap<[u8; 8], EventDef>,
+    types: HashMap<String, TypeDef>,
+}
+
+struct InstructionDef {
+    name: String,
+    account_names: Vec<String>,
+    args: Vec<(String, IdlType)>,
+}
+
+struct EventDef {
+    name: String,
+    fields: Vec<(String, IdlType)>,
+}
+
+enum TypeDef {
+    Struct(Vec<(String, IdlType)>),
+    TupleStruct(Vec<IdlType>),
+    Enum(Vec<EnumVariant>),
+    Alias(IdlType),
+}
+
+struct EnumVariant {
+    name: String,
+    fields: VariantFields,
+}
+
+enum VariantFields {
+    Unit,
+    Named(Vec<(String, IdlType)>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Clone)]
+enum IdlType {
+    Bool,
+    Int { bytes: usize, signed: bool },
+    F32,
+    F64,
+    String,
+    Bytes,
+    Pubkey,
+    Option(Box<IdlType>),
+    COption(Box<IdlType>),          // spl's 4 byte tagged option
+    Vec(Box<IdlType>),
+    Array(Box<IdlType>, usize),
+    Defined(String),
+}
+
+// an instruction decoded with its program's IDL
+pub(crate) struct AnchorInstruction<'a> {
+    pub program: &'a str,
+    pub name: &'a str,
+    pub account_names: &'a [String],
+    pub args: Vec<(&'a str, Value)>,
+}
+
+// an event decoded from a transaction's logs with its program's IDL
+pub(crate) struct AnchorEvent<'a> {
+    pub program_id: Pubkey,
+    pub program: &'a str,
+    pub name: &'a str,
+    pub fields: Vec<(&'a str, Value)>,
+}
+
+// the IDL JSON, in both the older (camelCase names, "publicKey", "metadata.address")
+// & newer (snake_case names, "pubkey", "address", explicit discriminators) formats
+#[derive(Deserialize)]
+struct IdlJson {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    address: Option<String>,
+    #[serde(default)]
+    metadata: Option<IdlMetadataJson>,
+    #[serde(default)]
+    instructions: Vec<InstructionJson>,
+    #[serde(default)]
+    accounts: Vec<TypeDefJson>,
+    #[serde(default)]
+    types: Vec<TypeDefJson>,
+    #[serde(default)]
+    events: Vec<EventJson>,
+}
+
+#[derive(Deserialize)]
+struct IdlMetadataJson {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InstructionJson {
+    name: String,
+    #[serde(default)]
+    accounts: Vec<AccountItemJson>,
+    #[serde(default)]
+    args: Vec<FieldJson>,
+    #[serde(default)]
+    discriminator: Option<Vec<u8>>,
+}
+
+// an account, or a named group of them
+#[derive(Deserialize)]
+struct AccountItemJson {
+    name: String,
+    #[serde(default)]
+    accounts: Option<Vec<AccountItemJson>>,
+}
+
+#[derive(Deserialize)]
+struct FieldJson {
+    name: String,
+    #[serde(rename = "type")]
+    ty: Value,
+}
+
+#[derive(Deserialize)]
+struct TypeDefJson {
+    name: String,
+    #[serde(rename = "type", default)]
+    ty: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct EventJson {
+    name: String,
+    #[serde(default)]
+    fields: Option<Vec<FieldJson>>,
+    #[serde(default)]
+    discriminator: Option<Vec<u8>>,
+}
+
+fn discriminator(namespace: &str, name: &str, explicit: &Option<Vec<u8>>) -> Result<[u8; 8], String> {
+    match explicit {
+        Some(bytes) => bytes.as_slice().try_into().map_err(|_| format!("{}'s discriminator isn't 8 bytes", name)),
+        None => {
+            let mut disc = [0u8; 8];
+            disc.copy_from_slice(&hashv(&[namespace.as_bytes(), b":", name.as_bytes()]).to_bytes()[..8]);
+            Ok(disc)
+        }
+    }
+}
+
+fn parse_type(ty: &Value) -> Result<IdlType, String> {
+    let int = |bytes, signed| Ok(IdlType::Int { bytes, signed });
+    match ty {
+        Value::String(name) => match name.as_str() {
+            "bool" => Ok(IdlType::Bool),
+            "u8" => int(1, false), "i8" => int(1, true),
+            "u16" => int(2, false), "i16" => int(2, true),
+            "u32" => int(4, false), "i32" => int(4, true),
+            "u64" => int(8, false), "i64" => int(8, true),
+            "u128" => int(16, false), "i128" => int(16, true),
+            "f32" => Ok(IdlType::F32),
+            "f64" => Ok(IdlType::F64),
+            "string" => Ok(IdlType::String),
+            "bytes" => Ok(IdlType::Bytes),
+            "publicKey" | "pubkey" => Ok(IdlType::Pubkey),
+            other => Err(format!("unsupported IDL type '{}'", other)),
+        },
+        Value::Object(obj) => {
+            let inner = |key: &str| obj.get(key).map(|t| parse_type(t).map(Box::new));
+            if let Some(t) = inner("option") { return Ok(IdlType::Option(t?)) }
+            if let Some(t) = inner("coption") { return Ok(IdlType::COption(t?)) }
+            if let Some(t) = inner("vec") { return Ok(IdlType::Vec(t?)) }
+            if let Some(array) = obj.get("array").and_then(|a| a.as_array()) {
+                let len = array.get(1).and_then(|l| l.as_u64()).ok_or("array types need a fixed length")?;
+                let item = array.first().ok_or("array types need an item type")?;
+                return Ok(IdlType::Array(Box::new(parse_type(item)?), len as usize));
+            }
+            match obj.get("defined") {
+                Some(Value::String(name)) => Ok(IdlType::Defined(name.clone())),
+                Some(Value::Object(defined)) => match defined.get("name") {
+                    Some(Value::String(name)) => Ok(IdlType::Defined(name.clone())),
+                    _ => Err("defined types need a name".to_string()),
+                },
+                _ => Err(format!("unsupported IDL type {}", ty)),
+            }
+        },
+        _ => Err(format!("unsupported IDL type {}", ty)),
+    }
+}
+
+fn parse_fields(fields: &[FieldJson]) -> Result<Vec<(String, IdlType)>, String> {
+    fields.iter().map(|f| Ok((f.name.clone(), parse_type(&f.ty)?))).collect()
+}
+
+fn parse_field_values(fields: &Value) -> Result<Vec<(String, IdlType)>, String> {
+    let fields: Vec<FieldJson> = serde_json::from_value(fields.clone()).map_err(|e| e.to_string())?;
+    parse_fields(&fields)
+}
+
+fn parse_type_def(ty: &Value) -> Result<TypeDef, String> {
+    let kind = ty.get("kind").and_then(|k| k.as_str()).unwrap_or_default();
+    match kind {
+        "struct" => match ty.get("fields") {
+            None => Ok(TypeDef::Struct(vec![])),
+            // tuple structs list bare types, not named fields
+            Some(Value::Array(fields)) if fields.iter().any(|f| f.get("name").is_none()) =>
+                Ok(TypeDef::TupleStruct(fields.iter().map(parse_type).collect::<Result<_, _>>()?)),
+            Some(fields) => Ok(TypeDef::Struct(parse_field_values(fields)?)),
+        },
+        "enum" => {
+            let variants = ty.get("variants").and_then(|v| v.as_array()).ok_or("enum types need variants")?;
+            variants.iter().map(|v| {
+                let name = v.get("name").and_then(|n| n.as_str()).ok_or("enum variants need a name")?.to_string();
+                let fields = match v.get("fields").and_then(|f| f.as_array()) {
+                    None => VariantFields::Unit,
+                    Some(fields) if fields.iter().all(|f| f.get("name").is_some()) =>
+                        VariantFields::Named(parse_field_values(&Value::Array(fields.clone()))?),
+                    Some(fields) => VariantFields::Tuple(fields.iter().map(parse_type).collect::<Result<_, _>>()?),
+                };
+                Ok(EnumVariant { name, fields })
+            }).collect::<Result<Vec<_>, String>>().map(TypeDef::Enum)
+        },
+        "type" | "alias" => Ok(TypeDef::Alias(parse_type(ty.get("alias").or(ty.get("value")).unwrap_or(&Value::Null))?)),
+        other => Err(format!("unsupported IDL type kind '{}'", other)),
+    }
+}
+
+// "group.account" for accounts inside a group
+fn flatten_accounts(items: &[AccountItemJson], prefix: &str, names: &mut Vec<String>) {
+    items.iter().for_each(|item| {
+        let name = format!("{}{}", prefix, item.name);
+        match &item.accounts {
+            Some(group) => flatten_accounts(group, &format!("{}.", name), names),
+            None => names.push(name),
+        }
+    });
+}
+
+impl ProgramIdl {
+    fn parse(idl: &IdlJson) -> Result<ProgramIdl, String> {
+        let mut types = HashMap::new();
+        for def in idl.accounts.iter().chain(idl.types.iter()) {
+            if let Some(ty) = &def.ty {
+                types.insert(def.name.clone(), parse_type_def(ty).map_err(|e| format!("type {}:  {}", def.name, e))?);
+            }
+        }
+
+        let mut instructions = HashMap::new();
+        for ix in idl.instructions.iter() {
+            let args = parse_fields(&ix.args).map_err(|e| format!("instruction {}:  {}", ix.name, e))?;
+            let mut account_names = vec![];
+            flatten_accounts(&ix.accounts, "", &mut account_names);
+            let disc = discriminator("global", &snake_case(&ix.name), &ix.discriminator)?;
+            instructions.insert(disc, InstructionDef { name: ix.name.clone(), account_names, args });
+        }
+
+        let mut events = HashMap::new();
+        for event in idl.events.iter() {
+            // newer IDLs leave an event's fields to the type of the same name
+            let fields = match (&event.fields, types.get(&event.name)) {
+                (Some(fields), _) => parse_fields(fields).map_err(|e| format!("event {}:  {}", event.name, e))?,
+                (None, Some(TypeDef::Struct(fields))) => fields.clone(),
+                (None, _) => vec![],
+            };
+            let disc = discriminator("event", &event.name, &event.discriminator)?;
+            events.insert(disc, EventDef { name: event.name.clone(), fields });
+        }
+
+        let name = idl.name.clone()
+            .or_else(|| idl.metadata.as_ref().and_then(|m| m.name.clone()))
+            .unwrap_or_default();
+        Ok(ProgramIdl { name, instructions, events, types })
+    }
+}
+
+// how deeply defined types can nest.  resolving one reads no bytes, so without a limit
+// an IDL whose types refer to themselves (an alias cycle, or a struct holding itself) would never stop
+const MAX_TYPE_DEPTH: usize = 64;
+
+// reads Borsh encoded values, as laid out by an IDL's types
+struct BorshReader<'a> {
+    data: &'a [u8],
+    types: &'a HashMap<String, TypeDef>,
+    depth: usize,           // defined types being resolved
+}
+
+impl<'a> BorshReader<'a> {
+    fn new(data: &'a [u8], types: &'a HashMap<String, TypeDef>) -> BorshReader<'a> {
+        BorshReader { data, types, depth: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len { return Err(format!("data ends {} bytes early", len - self.data.len())) }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap_or_default()))
+    }
+
+    // the length prefix of a vec, string or bytes, checked against what's left so bad data can't make huge allocations
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.u32()? as usize;
+        if len > self.data.len() { return Err(format!("length {} is more than the {} bytes left", len, self.data.len())) }
+        Ok(len)
+    }
+
+    fn int(&mut self, bytes: usize, signed: bool) -> Result<Value, String> {
+        let raw = self.take(bytes)?;
+        let mut buf = [0u8; 16];
+        buf[..bytes].copy_from_slice(raw);
+        // sign extend
+        if signed && raw[bytes - 1] & 0x80 != 0 { buf[bytes..].iter_mut().for_each(|b| *b = 0xff) }
+        let (unsigned, signed_value) = (u128::from_le_bytes(buf), i128::from_le_bytes(buf));
+        // 128 bit values too big for a JSON number are written as strings
+        Ok(match signed {
+            false => u64::try_from(unsigned).map(Value::from).unwrap_or_else(|_| Value::from(unsigned.to_string())),
+            true => i64::try_from(signed_value).map(Value::from).unwrap_or_else(|_| Value::from(signed_value.to_string())),
+        })
+    }
+
+    fn fields(&mut self, fields: &[(String, IdlType)]) -> Result<Map<String, Value>, String> {
+        fields.iter().map(|(name, ty)| Ok((name.clone(), self.value(ty)?))).collect()
+    }
+
+    fn values(&mut self, types: &[IdlType]) -> Result<Value, String> {
+        types.iter().map(|ty| self.value(ty)).collect::<Result<Vec<_>, _>>().map(Value::Array)
+    }
+
+    fn repeated(&mut self, ty: &IdlType, len: usize) -> Result<Value, String> {
+        (0..len).map(|_| self.value(ty)).collect::<Result<Vec<_>, _>>().map(Value::Array)
+    }
+
+    fn defined(&mut self, name: &str) -> Result<Value, String> {
+        if self.depth >= MAX_TYPE_DEPTH { return Err(format!("type {} nests more than {} types deep", name, MAX_TYPE_DEPTH)) }
+        self.depth += 1;
+        let types = self.types;
+        let value = match types.get(name).ok_or(format!("type {} isn't in the IDL", name))? {
+            TypeDef::Struct(fields) => Value::Object(self.fields(fields)?),
+            TypeDef::TupleStruct(types) => self.values(types)?,
+            TypeDef::Alias(ty) => self.value(ty)?,
+            TypeDef::Enum(variants) => {
+                let index = self.take(1)?[0] as usize;
+                let variant = variants.get(index).ok_or(format!("{} has no variant {}", name, index))?;
+                // unit variants are just their name, others are {name: fields}
+                match &variant.fields {
+                    VariantFields::Unit => Value::String(variant.name.clone()),
+                    VariantFields::Named(fields) => json!({ &variant.name: Value::Object(self.fields(fields)?) }),
+                    VariantFields::Tuple(types) => json!({ &variant.name: self.values(types)? }),
+                }
+            },
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn value(&mut self, ty: &IdlType) -> Result<Value, String> {
+        Ok(match ty {
+            IdlType::Bool => Value::Bool(self.take(1)?[0] != 0),
+            IdlType::Int { bytes, signed } => self.int(*bytes, *signed)?,
+            IdlType::F32 => json!(f32::from_le_bytes(self.take(4)?.try_into().unwrap_or_default())),
+            IdlType::F64 => json!(f64::from_le_bytes(self.take(8)?.try_into().unwrap_or_default())),
+            IdlType::String => {
+                let len = self.len()?;
+                Value::String(String::from_utf8_lossy(self.take(len)?).to_string())
+            },
+            IdlType::Bytes => {
+                let len = self.len()?;
+                Value::String(bs58::encode(self.take(len)?).into_string())
+            },
+            IdlType::Pubkey => Value::String(bs58::encode(self.take(32)?).into_string()),
+            IdlType::Option(inner) => match self.take(1)?[0] {
+                0 => Value::Null,
+                _ => self.value(inner)?,
+            },
+            IdlType::COption(inner) => match self.u32()? {
+                0 => Value::Null,
+                _ => self.value(inner)?,
+            },
+            IdlType::Vec(inner) => {
+                let len = self.len()?;
+                self.repeated(inner, len)?
+            },
+            IdlType::Array(inner, len) => self.repeated(inner, *len)?,
+            IdlType::Defined(name) => self.defined(name)?,
+        })
+    }
+}
+
+impl IdlRegistry {
+    // each path is an IDL file, a dir of them, or '<program id>=<IDL file>'.
+    // otherwise the program id is the file's name, or else the address in the IDL
+    pub fn load(paths: &[String]) -> Result<IdlRegistry, String> {
+        let mut registry = IdlRegistry::default();
+        for path in paths {
+            if Path::new(path).is_dir() {
+                let rd = fs::read_dir(path).map_err(|e| format!("{}:  {}", path, e))?;
+                let mut files: Vec<_> = dir_file_paths(rd).into_iter()
+                    .filter(|p| p.extension().is_some_and(|e| e == "json"))
+                    .collect();
+                files.sort();
+                for file in files { registry.register(None, &file.to_string_lossy())? }
+                continue;
+            }
+            match path.split_once('=').map(|(id, file)| (Pubkey::from_str(id), file)) {
+                Some((Ok(id), file)) => registry.register(Some(id), file)?,
+                _ => registry.register(None, path)?,
+            }
+        }
+        Ok(registry)
+    }
+
+    fn register(&mut self, program_id: Option<Pubkey>, path: &str) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("{}:  {}", path, e))?;
+        let idl: IdlJson = serde_json::from_slice(&data).map_err(|e| format!("{}:  {}", path, e))?;
+
+        let file_stem = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let address = idl.address.clone().or_else(|| idl.metadata.as_ref().and_then(|m| m.address.clone()));
+        let program_id = program_id
+            .or_else(|| Pubkey::from_str(&file_stem).ok())
+            .or_else(|| address.and_then(|a| Pubkey::from_str(&a).ok()))
+            .ok_or(format!("{}:  no program id, name the file <program id>.json or use '<program id>={}'", path, path))?;
+
+        let mut program = ProgramIdl::parse(&idl).map_err(|e| format!("{}:  {}", path, e))?;
+        if program.name.is_empty() { program.name = file_stem }
+        self.programs.insert(program_id, program);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn program_name(&self, program_id: &Pubkey) -> Option<&str> {
+        self.programs.get(program_id).map(|p| p.name.as_str())
+    }
+
+    // Ok(None) for programs without a registered IDL
+    pub fn decode_instruction(&self, program_id: &Pubkey, data: &[u8]) -> Result<Option<AnchorInstruction<'_>>, String> {
+        let program = match self.programs.get(program_id) {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let disc: [u8; 8] = data.get(..8).and_then(|d| d.try_into().ok())
+            .ok_or(format!("{} instruction data is too short for a discriminator", program.name))?;
+        let ix = program.instructions.get(&disc)
+            .ok_or(format!("{} has no instruction with discriminator {}", program.name, bs58::encode(disc).into_string()))?;
+
+        let mut reader = BorshReader::new(&data[8..], &program.types);
+        let args = ix.args.iter()
+            .map(|(name, ty)| Ok((name.as_str(), reader.value(ty)?)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("{}::{} args:  {}", program.name, ix.name, e))?;
+        Ok(Some(AnchorInstruction { program: &program.name, name: &ix.name, account_names: &ix.account_names, args }))
+    }
+
+    // the events the transaction's registered programs logged, or why one couldn't be decoded
+    pub fn decode_events(&self, tx: &NormalizedTx) -> Vec<Result<AnchorEvent<'_>, String>> {
+        tx.invoke_logs().into_iter().filter_map(|log| {
+            let encoded = log.line.strip_prefix("Program data: ")?;
+            let program_id = log.program?;
+            let program = self.programs.get(&program_id)?;
+            Some(self.decode_event(program_id, program, encoded))
+        }).collect()
+    }
+
+    fn decode_event<'a>(&self, program_id: Pubkey, program: &'a ProgramIdl, encoded: &str) -> Result<AnchorEvent<'a>, String> {
+        let data = base64::decode(encoded.trim()).map_err(|e| format!("{} event:  {}", program.name, e))?;
+        let disc: [u8; 8] = data.get(..8).and_then(|d| d.try_into().ok())
+            .ok_or(format!("{} event data is too short for a discriminator", program.name))?;
+        let event = program.events.get(&disc)
+            .ok_or(format!("{} has no event with discriminator {}", program.name, bs58::encode(disc).into_string()))?;
+
+        let mut reader = BorshReader::new(&data[8..], &program.types);
+        let fields = event.fields.iter()
+            .map(|(name, ty)| Ok((name.as_str(), reader.value(ty)?)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("{}::{} fields:  {}", program.name, event.name, e))?;
+        Ok(AnchorEvent { program_id, program: &program.name, name: &event.name, fields })
+    }
+}
+
+// the IDLs from the --idl args, or from IDL_DIR without any
+pub(crate) fn load_idl_args(args: &CliArguments) -> Result<IdlRegistry, String> {
+    match (args.idl.is_empty(), Path::new(IDL_DIR).is_dir()) {
+        (false, _) => IdlRegistry::load(&args.idl),
+        (true, true) => IdlRegistry::load(&[IDL_DIR.to_string()]),
+        (true, false) => Ok(IdlRegistry::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(defs: Value) -> HashMap<String, TypeDef> {
+        let defs: Vec<TypeDefJson> = serde_json::from_value(defs).unwrap();
+        defs.iter().map(|d| (d.name.clone(), parse_type_def(d.ty.as_ref().unwrap()).unwrap())).collect()
+    }
+
+    fn read_with(types: &HashMap<String, TypeDef>, ty: Value, data: &[u8]) -> Result<Value, String> {
+        BorshReader::new(data, types).value(&parse_type(&ty).unwrap())
+    }
+
+    fn read(ty: Value, data: &[u8]) -> Result<Value, String> {
+        read_with(&HashMap::new(), ty, data)
+    }
+
+    #[test]
+    fn ints_are_sign_extended() {
+        assert_eq!(read(json!("u8"), &[0xff]), Ok(json!(255)));
+        assert_eq!(read(json!("i8"), &[0xff]), Ok(json!(-1)));
+        assert_eq!(read(json!("i16"), &[0xfe, 0xff]), Ok(json!(-2)));
+        assert_eq!(read(json!("u32"), &[1, 1, 0, 0]), Ok(json!(257)));
+        assert_eq!(read(json!("i64"), &(-5i64).to_le_bytes()), Ok(json!(-5)));
+        assert_eq!(read(json!("u64"), &u64::MAX.to_le_bytes()), Ok(json!(u64::MAX)));
+    }
+
+    #[test]
+    fn big_128_bit_ints_are_strings() {
+        assert_eq!(read(json!("u128"), &7u128.to_le_bytes()), Ok(json!(7)));
+        assert_eq!(read(json!("i128"), &(-7i128).to_le_bytes()), Ok(json!(-7)));
+        assert_eq!(read(json!("u128"), &u128::MAX.to_le_bytes()), Ok(json!(u128::MAX.to_string())));
+        assert_eq!(read(json!("i128"), &i128::MIN.to_le_bytes()), Ok(json!(i128::MIN.to_string())));
+    }
+
+    #[test]
+    fn options_vecs_and_arrays() {
+        assert_eq!(read(json!({"option": "u16"}), &[0]), Ok(Value::Null));
+        assert_eq!(read(json!({"option": "u16"}), &[1, 5, 0]), Ok(json!(5)));
+        assert_eq!(read(json!({"coption": "u8"}), &[0, 0, 0, 0]), Ok(Value::Null));
+        assert_eq!(read(json!({"coption": "u8"}), &[1, 0, 0, 0, 7]), Ok(json!(7)));
+        assert_eq!(read(json!({"vec": "u8"}), &[3, 0, 0, 0, 1, 2, 3]), Ok(json!([1, 2, 3])));
+        assert_eq!(read(json!({"vec": "bool"}), &[0, 0, 0, 0]), Ok(json!([])));
+        assert_eq!(read(json!({"array": ["u16", 2]}), &[1, 0, 2, 0]), Ok(json!([1, 2])));
+        assert_eq!(read(json!("string"), &[2, 0, 0, 0, b'g', b'm']), Ok(json!("gm")));
+    }
+
+    #[test]
+    fn named_and_tuple_enums() {
+        let types = types(json!([{
+            "name": "Order",
+            "type": {"kind": "enum", "variants": [
+                {"name": "Cancel"},
+                {"name": "Limit", "fields": [{"name": "price", "type": "u64"}, {"name": "postOnly", "type": "bool"}]},
+                {"name": "Market", "fields": ["u32", {"option": "u8"}]},
+            ]},
+        }]));
+        let order = json!({"defined": "Order"});
+
+        assert_eq!(read_with(&types, order.clone(), &[0]), Ok(json!("Cancel")));
+        assert_eq!(read_with(&types, order.clone(), &[1, 9, 0, 0, 0, 0, 0, 0, 0, 1]),
+                   Ok(json!({"Limit": {"price": 9, "postOnly": true}})));
+        assert_eq!(read_with(&types, order.clone(), &[2, 4, 0, 0, 0, 0]), Ok(json!({"Market": [4, null]})));
+        assert!(read_with(&types, order, &[3]).unwrap_err().contains("no variant 3"));
+    }
+
+    #[test]
+    fn short_data_is_an_error() {
+        assert_eq!(read(json!("u64"), &[1, 2, 3]), Err("data ends 5 bytes early".to_string()));
+        assert!(read(json!({"option": "u32"}), &[1, 0]).is_err());
+        // a length prefix longer than the data left is rejected before anything is read
+        assert!(read(json!({"vec": "u8"}), &[0xff, 0xff, 0xff, 0xff, 1]).unwrap_err().starts_with("length"));
+    }
+
+    #[test]
+    fn self_referring_types_are_an_error() {
+        let types = types(json!([
+            {"name": "A", "type": {"kind": "type", "alias": {"defined": "B"}}},
+            {"name": "B", "type": {"kind": "type", "alias": {"defined": "A"}}},
+            {"name": "Node", "type": {"kind": "struct", "fields": [{"name": "next", "type": {"defined": "Node"}}]}},
+        ]));
+
+        assert!(read_with(&types, json!({"defined": "A"}), &[0; 64]).unwrap_err().contains("types deep"));
+        assert!(read_with(&types, json!({"defined": "Node"}), &[0; 64]).unwrap_err().contains("types deep"));
+    }
+
+    // the same program in the older IDL format, where discriminators are worked out from the names,
+    // & the newer one, where Anchor lists them:  sha256("global:initialize")[..8] is Anchor's well known initialize
+    fn old_idl() -> Value {
+        json!({
+            "name": "swap",
+            "instructions": [
+                {"name": "initialize", "accounts": [], "args": []},
+                {"name": "swapTokens", "accounts": [{"name": "user"}, {"name": "pool", "accounts": [{"name": "vault"}]}],
+                 "args": [{"name": "amountIn", "type": "u64"}, {"name": "owner", "type": "publicKey"}]},
+            ],
+            "events": [{"name": "SwapEvent", "fields": [{"name": "amount", "type": "u64", "index": false}]}],
+            "metadata": {"address": "EvAEMwfZKdXMqKHVBDnhCyDp55ZkEUGZUttES6La9mXe"},
+        })
+    }
+
+    fn new_idl() -> Value {
+        json!({
+            "address": "EvAEMwfZKdXMqKHVBDnhCyDp55ZkEUGZUttES6La9mXe",
+            "metadata": {"name": "swap"},
+            "instructions": [
+                {"name": "initialize", "discriminator": [175, 175, 109, 31, 13, 152, 155, 237], "accounts": [], "args": []},
+                {"name": "swap_tokens", "discriminator": [201, 226, 234, 16, 70, 155, 131, 206],
+                 "accounts": [{"name": "user"}, {"name": "pool", "accounts": [{"name": "vault"}]}],
+                 "args": [{"name": "amount_in", "type": "u64"}, {"name": "owner", "type": "pubkey"}]},
+            ],
+            "events": [{"name": "SwapEvent", "discriminator": [64, 198, 205, 232, 38, 8, 113, 226]}],
+            "types": [{"name": "SwapEvent", "type": {"kind": "struct", "fields": [{"name": "amount", "type": "u64"}]}}],
+        })
+    }
+
+    fn registry(idl: Value) -> (Pubkey, IdlRegistry) {
+        let program_id = Pubkey::from_str("EvAEMwfZKdXMqKHVBDnhCyDp55ZkEUGZUttES6La9mXe").unwrap();
+        let program = ProgramIdl::parse(&serde_json::from_value(idl).unwrap()).unwrap();
+        (program_id, IdlRegistry { programs: HashMap::from([(program_id, program)]) })
+    }
+
+    #[test]
+    fn discriminators_match_anchor() {
+        let old = registry(old_idl()).1;
+        let disc = |ns, name| discriminator(ns, name, &None).unwrap();
+
+        assert_eq!(disc("global", "initialize"), [175, 175, 109, 31, 13, 152, 155, 237]);
+        // camelCase names are hashed as Anchor's snake_case function names
+        let program = old.programs.values().next().unwrap();
+        assert_eq!(program.instructions[&[201, 226, 234, 16, 70, 155, 131, 206]].name, "swapTokens");
+        assert_eq!(program.events[&[64, 198, 205, 232, 38, 8, 113, 226]].name, "SwapEvent");
+        assert!(discriminator("global", "x", &Some(vec![1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn old_and_new_idl_formats_decode_the_same() {
+        let owner = Pubkey::new_unique();
+        let mut data = vec![201, 226, 234, 16, 70, 155, 131, 206];
+        data.extend(1000u64.to_le_bytes());
+        data.extend(owner.to_bytes());
+
+        for idl in [old_idl(), new_idl()] {
+            let (program_id, registry) = registry(idl);
+            let ix = registry.decode_instruction(&program_id, &data).unwrap().unwrap();
+            assert_eq!(ix.program, "swap");
+            assert_eq!(ix.account_names, &["user".to_string(), "pool.vault".to_string()]);
+            assert_eq!(ix.args.iter().map(|(_, v)| v.clone()).collect::<Vec<_>>(), vec![json!(1000), json!(owner.to_string())]);
+
+            let program = &registry.programs[&program_id];
+            let event = program.events.get(&[64, 198, 205, 232, 38, 8, 113, 226]).unwrap();
+            assert_eq!(event.fields.len(), 1);
+            assert!(registry.decode_instruction(&program_id, &data[..4]).is_err());
+            assert!(registry.decode_instruction(&Pubkey::new_unique(), &data).unwrap().is_none());
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{fs, path::Path, process::exit};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use solana_transaction_status::{
+    ConfirmedBlock, EncodedConfirmedBlock, TransactionWithStatusMeta, TransactionStatusMeta,
+    UiTransactionStatusMeta, UiInstruction, InnerInstructions, TransactionTokenBalance,
+    UiTransactionTokenBalance, UiTransactionEncoding
+};
+use solana_program::instruction::CompiledInstruction;
+
+use crate::{
+    cli::CliArguments, compression::read_file, manifest::{ChunkEntry, update_manifest},
+    files::{
+        SlotData, ChunkFormat, CHUNKED_BLOCKS_DIR,
+        chunk_file_paths, parse_chunk_label, load_blocks_chunk, write_blocks_chunk
+    },
+    util::{log_err, log_err_none, timer, do_or_die}
+};
+
+
+// every binary chunk file starts with a header, then a bincode-encoded Vec of its blocks.
+// the magic bytes say which kind of blocks follow:  Vec<(u64, ConfirmedBlock)> here,
+// or Vec<NormalizedBlock> for normalized chunks.
+// bump the version whenever the layout after the header changes
+pub(crate) const BLOCKS_MAGIC: [u8; 4] = *b"SOLC";
+pub(crate) const NORMALIZED_MAGIC: [u8; 4] = *b"SOLN";
+pub(crate) const CHUNK_FORMAT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ChunkHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub first_slot: u64,
+    pub last_slot: u64,
+    pub block_count: u64,
+}
+
+impl ChunkHeader {
+    pub fn new(magic: [u8; 4], chunk: &[SlotData]) -> ChunkHeader {
+        ChunkHeader {
+            magic,
+            version: CHUNK_FORMAT_VERSION,
+            first_slot: chunk.first().map_or(0, |s| s.0),
+            last_slot: chunk.last().map_or(0, |s| s.0),
+            block_count: chunk.len() as u64,
+        }
+    }
+
+    pub fn check(&self, magic: [u8; 4]) -> Result<(), String> {
+        if self.magic != magic {
+            return Err(format!("expected a chunk starting with {:?}, found {:?}", magic, self.magic))
+        }
+        if self.version != CHUNK_FORMAT_VERSION {
+            return Err(format!("binary chunk format version {} not supported, expected {}",
+                               self.version, CHUNK_FORMAT_VERSION))
+        }
+        Ok(())
+    }
+}
+
+// EncodedConfirmedBlock can't round-trip through bincode (its transactions are untagged enums),
+// so binary chunks store the decoded ConfirmedBlock it was encoded from.
+// a transaction that can't be decoded fails the whole block, rather than being left out of it
+fn to_confirmed_block(slot: u64, block: &EncodedConfirmedBlock) -> Result<ConfirmedBlock, String> {
+    let transactions = block.transactions.iter().enumerate().map(|(i, etx)| {
+        let transaction = etx.transaction.decode()
+            .ok_or(format!("slot {}, transaction {} didn't decode", slot, i))?;
+        let meta = etx.meta.as_ref().map(to_status_meta).transpose()
+            .map_err(|e| format!("slot {}, transaction {}:  {}", slot, i, e))?;
+        Ok(TransactionWithStatusMeta { transaction, meta })
+    }).collect::<Result<Vec<_>, String>>()?;
+
+    Ok(ConfirmedBlock {
+        previous_blockhash: block.previous_blockhash.clone(),
+        blockhash: block.blockhash.clone(),
+        parent_slot: block.parent_slot,
+        transactions,
+        rewards: block.rewards.clone(),
+        block_time: block.block_time,
+        block_height: block.block_height,
+    })
+}
+
+pub(crate) fn to_status_meta(meta: &UiTransactionStatusMeta) -> Result<TransactionStatusMeta, String> {
+    let inner_instructions = match meta.inner_instructions.as_ref() {
+        Some(inner) => Some(inner.iter().map(|ixs| Ok(InnerInstructions {
+            index: ixs.index,
+            instructions: ixs.instructions.iter().map(to_compiled_instruction).collect::<Result<_, String>>()?,
+        })).collect::<Result<_, String>>()?),
+        None => None,
+    };
+    Ok(TransactionStatusMeta {
+        status: meta.status.clone(),
+        fee: meta.fee,
+        pre_balances: meta.pre_balances.clone(),
+        post_balances: meta.post_balances.clone(),
+        inner_instructions,
+        log_messages: meta.log_messages.clone(),
+        pre_token_balances: meta.pre_token_balances.as_ref().map(|b| to_token_balances(b)),
+        post_token_balances: meta.post_token_balances.as_ref().map(|b| to_token_balances(b)),
+        rewards: meta.rewards.clone(),
+    })
+}
+
+// blocks are scraped with base64 encoding, so inner instructions should always be the compiled kind
+fn to_compiled_instruction(ix: &UiInstruction) -> Result<CompiledInstruction, String> {
+    match ix {
+        UiInstruction::Compiled(c) => match bs58::decode(&c.data).into_vec() {
+            Ok(data) => Ok(CompiledInstruction {
+                program_id_index: c.program_id_index, accounts: c.accounts.clone(), data
+            }),
+            Err(e) => Err(format!("inner instruction data:  {}", e)),
+        },
+        UiInstruction::Parsed(_) => Err("inner instruction is parsed, not compiled".to_string()),
+    }
+}
+
+fn to_token_balances(balances: &[UiTransactionTokenBalance]) -> Vec<TransactionTokenBalance> {
+    balances.iter().map(|b| TransactionTokenBalance {
+        account_index: b.account_index,
+        mint: b.mint.clone(),
+        ui_token_amount: b.ui_token_amount.clone(),
+        owner: b.owner.clone().unwrap_or_default(),
+    }).collect()
+}
+
+pub(crate) fn serialize_with_header<T: Serialize>(header: &ChunkHeader, blocks: &T) 
+    -> Result<Vec<u8>, bincode::Error> 
+{
+    let mut data = bincode::serialize(header)?;
+    bincode::serialize_into(&mut data, blocks)?;
+    Ok(data)
+}
+
+// read a binary chunk file of any compression, checking its header before the blocks
+pub(crate) fn deserialize_with_header<P: AsRef<Path>, T: DeserializeOwned>(path: P, magic: [u8; 4]) 
+    -> Result<(ChunkHeader, T), String> 
+{
+    let data = read_file(&path).map_err(|e| e.to_string())?;
+
+    let mut reader = data.as_slice();
+    let header = bincode::deserialize_from::<_, ChunkHeader>(&mut reader).map_err(|e| e.to_string())?;
+    header.check(magic)?;
+
+    let blocks = bincode::deserialize_from::<_, T>(&mut reader).map_err(|e| e.to_string())?;
+    Ok((header, blocks))
+}
+
+pub(crate) fn serialize_bin_chunk(chunk: &[SlotData]) -> Result<Vec<u8>, String> {
+    let blocks: Vec<(u64, ConfirmedBlock)> = chunk.iter()
+        .map(|(slot, ecb)| Ok((*slot, to_confirmed_block(*slot, ecb)?)))
+        .collect::<Result<_, String>>()?;
+
+    serialize_with_header(&ChunkHeader::new(BLOCKS_MAGIC, chunk), &blocks).map_err(|e| e.to_string())
+}
+
+// loads back into the same (slot, EncodedConfirmedBlock) shape a json chunk has,
+// so analyses don't need to know which format their data came from
+pub fn load_blocks_chunk_bin<P: AsRef<Path>>(path: P) -> Option<Vec<SlotData>> {
+    match deserialize_with_header::<_, Vec<(u64, ConfirmedBlock)>>(path, BLOCKS_MAGIC) {
+        Ok((_, blocks)) => Some(blocks.into_iter()
+            .map(|(slot, block)| (slot, block.encode(UiTransactionEncoding::Base64)))
+            .collect()),
+        Err(e) => log_err_none(&e)
+    }
+}
+
+const NO_DIR_EXIT_MSG: &str = "can't convert chunks without a valid source directory!\nexiting\n";
+
+// handler for the 'convert_chunks' CLI task:
+// re-write every chunk in the source dir (json chunks by default) in another format (binary by default)
+pub(crate) fn convert_chunks_cli(args: &CliArguments) {
+    let src_dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    do_or_die(|| fs::metadata(src_dir), NO_DIR_EXIT_MSG);
+    let src_paths = chunk_file_paths(src_dir);
+    let format = args.chunk_format.unwrap_or(ChunkFormat::Binary);
+    let out_dir = format.default_dir();
+    if let Err(e) = fs::create_dir_all(out_dir) { return log_err(&e) }
+
+    println!("converting {} chunks in {} to {:?}, in {}", src_paths.len(), src_dir, format, out_dir);
+    println!("chunk file compression:  {:?}", args.compression);
+
+    let mut failed = Vec::<String>::new();
+    let elapsed = timer(|| {
+        let results: Vec<Result<Option<ChunkEntry>, String>> = src_paths.par_iter().map(|path| {
+            let chunk = load_blocks_chunk(path).ok_or(format!("chunk load failed, path:  {:?}", path))?;
+            // converted chunks keep the source chunk's label
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            write_blocks_chunk(out_dir, &chunk, parse_chunk_label(&name), format, args.compression)
+                .map_err(|e| format!("{:?}:  {}", path, e))
+        }).collect();
+
+        let mut entries = Vec::<ChunkEntry>::new();
+        results.into_iter().for_each(|result| match result {
+            Ok(entry) => entries.extend(entry),
+            Err(e) => failed.push(e),
+        });
+        update_manifest(out_dir, entries, &[]);
+    });
+    println!("done, time:  {:3} seconds", elapsed.as_secs_f32());
+
+    // a chunk that can't be converted in full isn't written at all, so the conversion as a whole fails
+    if !failed.is_empty() {
+        log_err(&format!("{} of {} chunks couldn't be converted:", failed.len(), src_paths.len()));
+        failed.iter().for_each(|e| log_err(&format!("    {}", e)));
+        exit(1);
+    }
+}
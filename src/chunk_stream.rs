@@ -0,0 +1,181 @@
+use std::{io::{self, BufRead, BufReader, Read}, marker::PhantomData, path::Path};
+
+use serde::{Deserialize, de::DeserializeOwned};
+use solana_transaction_status::{ConfirmedBlock, UiTransactionEncoding};
+
+use crate::{
+    binary_chunk::{ChunkHeader, BLOCKS_MAGIC, NORMALIZED_MAGIC},
+    compression::open_reader,
+    files::{SlotData, ChunkFormat},
+    normalized::{NormalizedBlock, VoteFilter, normalize_block, denormalize_block},
+    slot_range::SlotRange
+};
+
+
+// a chunk file's blocks, read & parsed one at a time, so only 1 block is ever held in memory.
+// a block that fails to parse ends the stream with its error
+pub(crate) type ChunkStream<T> = Box<dyn Iterator<Item = Result<T, String>>>;
+
+// decompressed reads are buffered, since parsing asks for a few bytes at a time
+type ChunkReader = BufReader<Box<dyn Read>>;
+
+fn open_chunk_reader<P: AsRef<Path>>(path: P) -> Result<ChunkReader, String> {
+    open_reader(&path).map(BufReader::new).map_err(|e| format!("{:?}:  {}", path.as_ref(), e))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum JsonArrayState {
+    Start,          // before the opening '['
+    First,          // before the first block, or an empty array's ']'
+    Rest,           // before a ',' & the next block, or the closing ']'
+    Done,
+}
+
+// a json chunk is one array of [slot, block] pairs.
+// the array's brackets & commas are read here, & each pair in between is handed to serde_json
+pub(crate) struct JsonChunkStream {
+    reader: ChunkReader,
+    state: JsonArrayState,
+}
+
+impl JsonChunkStream {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JsonChunkStream, String> {
+        Ok(JsonChunkStream { reader: open_chunk_reader(path)?, state: JsonArrayState::Start })
+    }
+
+    // the next byte that isn't whitespace, without consuming it
+    fn peek_token(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let byte = match self.reader.fill_buf()?.first() {
+                Some(b) => *b,
+                None => return Ok(None),
+            };
+            if !byte.is_ascii_whitespace() { return Ok(Some(byte)) }
+            self.reader.consume(1);
+        }
+    }
+
+    fn next_token(&mut self) -> io::Result<Option<u8>> {
+        let token = self.peek_token()?;
+        if token.is_some() { self.reader.consume(1) }
+        Ok(token)
+    }
+
+    // a pair ends with its own ']', so serde_json never reads past it
+    fn read_block(&mut self) -> Result<SlotData, String> {
+        let mut de = serde_json::Deserializer::from_reader(&mut self.reader);
+        SlotData::deserialize(&mut de).map_err(|e| e.to_string())
+    }
+
+    fn advance(&mut self) -> Result<Option<SlotData>, String> {
+        let unexpected = |token: Option<u8>| format!("malformed json chunk, found {:?}", token.map(char::from));
+        loop {
+            match self.state {
+                JsonArrayState::Start => match self.next_token().map_err(|e| e.to_string())? {
+                    Some(b'[') => self.state = JsonArrayState::First,
+                    token => return Err(unexpected(token)),
+                },
+                JsonArrayState::First => match self.peek_token().map_err(|e| e.to_string())? {
+                    Some(b']') => self.state = JsonArrayState::Done,
+                    _ => {
+                        self.state = JsonArrayState::Rest;
+                        return self.read_block().map(Some)
+                    },
+                },
+                JsonArrayState::Rest => match self.next_token().map_err(|e| e.to_string())? {
+                    Some(b',') => return self.read_block().map(Some),
+                    Some(b']') => self.state = JsonArrayState::Done,
+                    token => return Err(unexpected(token)),
+                },
+                JsonArrayState::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Iterator for JsonChunkStream {
+    type Item = Result<SlotData, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.advance();
+        if next.is_err() { self.state = JsonArrayState::Done }
+        next.transpose()
+    }
+}
+
+// a binary chunk is a header, then a bincode Vec:  its length as a u64, then each of its items
+pub(crate) struct BinChunkStream<T> {
+    reader: ChunkReader,
+    remaining: u64,
+    item: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> BinChunkStream<T> {
+    pub fn open<P: AsRef<Path>>(path: P, magic: [u8; 4]) -> Result<BinChunkStream<T>, String> {
+        let mut reader = open_chunk_reader(path)?;
+        let header = bincode::deserialize_from::<_, ChunkHeader>(&mut reader).map_err(|e| e.to_string())?;
+        header.check(magic)?;
+        let remaining = bincode::deserialize_from::<_, u64>(&mut reader).map_err(|e| e.to_string())?;
+        Ok(BinChunkStream { reader, remaining, item: PhantomData })
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for BinChunkStream<T> {
+    type Item = Result<T, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 { return None }
+        match bincode::deserialize_from::<_, T>(&mut self.reader) {
+            Ok(item) => {
+                self.remaining -= 1;
+                Some(Ok(item))
+            },
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e.to_string()))
+            }
+        }
+    }
+}
+
+// stream a chunk of any format & compression as raw blocks, like load_blocks_chunk() but 1 at a time
+pub(crate) fn stream_blocks_chunk<P: AsRef<Path>>(path: P) -> Result<ChunkStream<SlotData>, String> {
+    Ok(match ChunkFormat::from_path(&path) {
+        ChunkFormat::Json => Box::new(JsonChunkStream::open(path)?),
+        ChunkFormat::Binary => Box::new(BinChunkStream::<(u64, ConfirmedBlock)>::open(path, BLOCKS_MAGIC)?
+            .map(|r| r.map(|(slot, block)| (slot, block.encode(UiTransactionEncoding::Base64))))),
+        ChunkFormat::Normalized => Box::new(BinChunkStream::<NormalizedBlock>::open(path, NORMALIZED_MAGIC)?
+            .map(|r| r.map(|block| denormalize_block(&block)))),
+    })
+}
+
+// stream a chunk of any format as normalized blocks, normalizing on the way if it isn't already
+pub(crate) fn stream_normalized_chunk<P: AsRef<Path>>(path: P) -> Result<ChunkStream<NormalizedBlock>, String> {
+    Ok(match ChunkFormat::from_path(&path) {
+        ChunkFormat::Normalized => Box::new(BinChunkStream::<NormalizedBlock>::open(path, NORMALIZED_MAGIC)?),
+        _ => Box::new(stream_blocks_chunk(path)?.map(|r| r.and_then(|(slot, ecb)| normalize_block(slot, &ecb)))),
+    })
+}
+
+// only the chunk's blocks inside the range, for chunks only partially inside it
+pub(crate) fn stream_normalized_chunk_in_range<P: AsRef<Path>>(path: P, range: &SlotRange)
+    -> Result<ChunkStream<NormalizedBlock>, String>
+{
+    let stream = stream_normalized_chunk(path)?;
+    if range.is_unbounded() { return Ok(stream) }
+
+    let range = *range;
+    Ok(Box::new(stream.filter(move |r| match r {
+        Ok(block) => range.contains(block.slot, block.block_time),
+        Err(_) => true,
+    })))
+}
+
+// leave out the transactions the vote filter doesn't keep, still streaming every block
+pub(crate) fn filter_votes(stream: ChunkStream<NormalizedBlock>, votes: VoteFilter) -> ChunkStream<NormalizedBlock> {
+    if votes == VoteFilter::Include { return stream }
+    Box::new(stream.map(move |r| r.map(|mut block| {
+        block.transactions.retain(|tx| votes.keeps(tx));
+        block
+    })))
+}
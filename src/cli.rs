@@ -1,7 +1,9 @@
 use clap::{self, Arg, App, ArgMatches};
+use solana_program::clock::UnixTimestamp;
 
 use crate::{
-    tasks::*, networks::expand_rpc_keywords,
+    tasks::*, networks::expand_rpc_keywords, compression::Compression, files::ChunkFormat,
+    slot_range::{SlotRange, parse_timestamp}, normalized::{AccountRole, VoteFilter},
     util::{log_err, log_err_none, println_each_indent} 
 };
 
@@ -12,7 +14,27 @@ pub(crate) struct CliArguments {
     pub rpc: Option<String>,
     pub source: Option<String>,
     pub chunk_size: Option<usize>,
-    pub sample_rate: Option<usize>
+    pub sample_rate: Option<usize>,
+    pub compression: Compression,
+    pub chunk_format: Option<ChunkFormat>,
+    pub slot_range: SlotRange,
+    pub max_gap: Option<u64>,
+    pub merge_tail: bool,
+    pub dest: Option<String>,
+    pub slot_count: Option<u64>,
+    pub epoch_chunks: bool,
+    pub time_window: Option<UnixTimestamp>,
+    pub archive: Option<String>,
+    pub dry_run: bool,
+    pub threads: Option<usize>,
+    pub task_args: Vec<String>,             // positional args after the task, e.g. analyzer names
+    pub top: Option<usize>,
+    pub role: Option<AccountRole>,
+    pub slot_window: Option<u64>,
+    pub exclude_fees: bool,
+    pub watch: Option<String>,
+    pub idl: Vec<String>,                   // Anchor IDL files or dirs, each maybe as '<program id>=<file>'
+    pub votes: VoteFilter,                  // whether analyzers see vote transactions, only them, or not
 }
 
 pub(crate) fn get_cli_args() -> CliArguments {
@@ -24,63 +46,214 @@ pub(crate) fn get_cli_args() -> CliArguments {
              .long("task")
              .short("t")
              .takes_value(true)
-             .required(true)
+             .required_unless("command")
              .help("Which sub-command to run"))
+    .arg(Arg::with_name("command")
+             .multiple(true)
+             .help("the task, if --task isn't given, followed by its arguments, e.g. 'analyze key_counts'"))
     .arg(Arg::with_name("minutes")
              .long("minutes")
              .short("m")
              .takes_value(true)
-             .required_if("task", SCRAPE_BLOCKS_TASK)
              .help("How long to run the task, in minutes"))
     .arg(Arg::with_name("rpc")
              .long("rpc")
              .short("r")
              .takes_value(true)
-             .required_if("task", SCRAPE_BLOCKS_TASK)
              .help("URL of the Solana RPC node to use, or: 1 of 'dev','test','main'"))
     .arg(Arg::with_name("source")
              .long("source")
              .short("s")
              .aliases(&["src"])
              .takes_value(true)
-             .required_if("task", COMPARE_BLOCK_LOADS_TASK)
              .help("path to read data from"))
+    .arg(Arg::with_name("dest")
+             .long("dest")
+             .short("d")
+             .takes_value(true)
+             .help("path to write data to"))
+    .arg(Arg::with_name("archive")
+             .long("archive")
+             .takes_value(true)
+             .help("move compacted slot files to this path, instead of deleting them"))
+    .arg(Arg::with_name("dry-run")
+             .long("dry-run")
+             .help("only report what would be done, without changing any files"))
     .arg(Arg::with_name("mb")
             .long("mb")
             .aliases(&["megabytes"])
             .takes_value(true)
-            .required_if("task", CHUNK_BLOCKS_TASK)
             .help("size in megabytes of grouped input, default: 2"))
+    .arg(Arg::with_name("slots")
+            .long("slots")
+            .takes_value(true)
+            .conflicts_with_all(&["mb", "epoch", "window"])
+            .help("chunk into aligned ranges of this many slots"))
+    .arg(Arg::with_name("epoch")
+            .long("epoch")
+            .conflicts_with_all(&["mb", "slots", "window"])
+            .help("chunk into 1 chunk per epoch"))
+    .arg(Arg::with_name("window")
+            .long("window")
+            .takes_value(true)
+            .conflicts_with_all(&["mb", "slots", "epoch"])
+            .help("chunk into aligned windows of block time, like 90s, 30m, 1h or 1d"))
     .arg(Arg::with_name("sample-rate")
             .long("sample-rate")
             .aliases(&["sr"])
             .takes_value(true)
-            .help("number of source files for each 1 copied to new sample, default: 50"));
+            .help("number of source files for each 1 copied to new sample, default: 50"))
+    .arg(Arg::with_name("compression")
+            .long("compression")
+            .short("c")
+            .takes_value(true)
+            .possible_values(&["zstd", "gzip", "none"])
+            .help("compression for written block & chunk files, default: none"))
+    .arg(Arg::with_name("level")
+            .long("level")
+            .takes_value(true)
+            .requires("compression")
+            .help("compression level, default: 3 for zstd, 6 for gzip"))
+    .arg(Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["json", "bin", "norm"])
+            .help("serialization of written chunk files, default: json (bin for convert_chunks)"))
+    .arg(Arg::with_name("max-gap")
+            .long("max-gap")
+            .takes_value(true)
+            .help("end a chunk wherever more than this many slots in a row are missing"))
+    .arg(Arg::with_name("merge-tail")
+            .long("merge-tail")
+            .help("merge an undersized last chunk with newly chunked blocks"))
+    .arg(Arg::with_name("threads")
+            .long("threads")
+            .takes_value(true)
+            .help("max chunks analyzed at once, default: 1 per cpu"))
+    .arg(Arg::with_name("top")
+            .long("top")
+            .takes_value(true)
+            .help("how many entries analyzers list in their top-N reports, default: 20"))
+    .arg(Arg::with_name("slot-window")
+            .long("slot-window")
+            .takes_value(true)
+            .help("slots in each window of analyzers' per-window series, default: 1000"))
+    .arg(Arg::with_name("role")
+            .long("role")
+            .takes_value(true)
+            .possible_values(&["fee_payer", "signer", "writable", "readonly"])
+            .help("only count public keys in this role, for key_counts"))
+    .arg(Arg::with_name("exclude-fees")
+            .long("exclude-fees")
+            .help("leave fees out of balance changes, for sol_flow"))
+    .arg(Arg::with_name("watch")
+            .long("watch")
+            .takes_value(true)
+            .help("only track these accounts:  comma separated pubkeys, or a file of them, for sol_flow"))
+    .arg(Arg::with_name("votes")
+            .long("votes")
+            .takes_value(true)
+            .possible_values(&["include", "exclude", "only"])
+            .help("whether analyses look at vote transactions, default: include"))
+    .arg(Arg::with_name("idl")
+            .long("idl")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Anchor IDL file, dir of them, or <program id>=<file>, repeatable, default: blocks/idl"))
+    .arg(Arg::with_name("from-slot")
+            .long("from-slot")
+            .takes_value(true)
+            .help("first slot analyzed, inclusive"))
+    .arg(Arg::with_name("to-slot")
+            .long("to-slot")
+            .takes_value(true)
+            .help("last slot analyzed, inclusive"))
+    .arg(Arg::with_name("since")
+            .long("since")
+            .takes_value(true)
+            .help("earliest block time analyzed, as unix seconds or UTC YYYY-MM-DD[THH:MM[:SS]]"))
+    .arg(Arg::with_name("until")
+            .long("until")
+            .takes_value(true)
+            .help("latest block time analyzed, as unix seconds or UTC YYYY-MM-DD[THH:MM[:SS]]"));
 
     let matches = app.get_matches();
 
-    let task = parse_task(&matches);
+    let (task, task_args) = parse_task(&matches);
+    check_required_args(&matches, &task);
     let minutes = parse_minutes(&matches);
     let rpc = parse_rpc(&matches);
     let source = parse_source(&matches);
     let chunk_size = parse_chunk_size(&matches);
     let sample_rate = parse_sample_rate(&matches);
+    let compression = parse_compression(&matches);
+    let chunk_format = parse_chunk_format(&matches);
+    let slot_range = parse_slot_range(&matches);
+    let max_gap = parse_max_gap(&matches);
+    let merge_tail = matches.is_present("merge-tail");
+    let dest = as_string_opt(matches.value_of("dest"));
+    let slot_count = parse_slot_count(&matches);
+    let epoch_chunks = matches.is_present("epoch");
+    let time_window = parse_time_window(&matches);
+    let archive = as_string_opt(matches.value_of("archive"));
+    let dry_run = matches.is_present("dry-run");
+    let threads = parse_threads(&matches);
+    let top = parse_top(&matches);
+    let role = parse_role(&matches);
+    let slot_window = parse_slot_window(&matches);
+    let exclude_fees = matches.is_present("exclude-fees");
+    let watch = as_string_opt(matches.value_of("watch"));
+    let votes = parse_votes(&matches);
+    let idl = matches.values_of("idl").map(|values| values.map(|v| v.to_string()).collect()).unwrap_or_default();
 
-    CliArguments { task, minutes, rpc, source, chunk_size, sample_rate }
+    CliArguments { 
+        task, minutes, rpc, source, chunk_size, sample_rate, compression, chunk_format, slot_range, max_gap, merge_tail,
+        dest, slot_count, epoch_chunks, time_window, archive, dry_run, threads,
+        task_args, top, role, slot_window, exclude_fees, watch, idl, votes
+    }
 }
 
-fn parse_task(matches: &ArgMatches) -> String {
-    match matches.value_of("task") {
-        Some(task_arg) => { 
-            if !TASK_NAMES.contains(&task_arg) { 
-                eprintln!("\ntask '{}' not recognized!", task_arg);
-                println!("available tasks:");
-                println_each_indent(&TASK_NAMES, true);  
-            }
-            task_arg.to_string()
-        },
-        _ => "".to_string(),
+// the task comes from --task, or else the first positional arg.
+// any other positional args are the task's own
+fn parse_task(matches: &ArgMatches) -> (String, Vec<String>) {
+    let mut positional: Vec<String> = matches.values_of("command")
+        .map(|values| values.map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+    let task = match matches.value_of("task") {
+        Some(task_arg) => task_arg.to_string(),
+        None if !positional.is_empty() => positional.remove(0),
+        None => "".to_string(),
+    };
+
+    if !TASK_NAMES.contains(&task.as_str()) { 
+        eprintln!("\ntask '{}' not recognized!", task);
+        println!("available tasks:");
+        println_each_indent(&TASK_NAMES, true);  
     }
+    (task, positional)
+}
+
+// the args each task can't run without.
+// checked once the task is known, since clap's required_if only sees --task, not a positional task
+const REQUIRED_TASK_ARGS: [(&str, &[&str]); 4] = [
+    (SCRAPE_BLOCKS_TASK, &["minutes", "rpc"]),
+    (COMPARE_BLOCK_LOADS_TASK, &["source"]),
+    (GET_BLOCK_PROD_TASK, &["rpc"]),
+    (RECHUNK_TASK, &["dest"]),
+];
+
+fn check_required_args(matches: &ArgMatches, task: &str) {
+    let missing: Vec<String> = REQUIRED_TASK_ARGS.iter()
+        .filter(|(required_by, _)| *required_by == task)
+        .flat_map(|(_, args)| args.iter())
+        .filter(|arg| !matches.is_present(arg))
+        .map(|arg| format!("--{}", arg))
+        .collect();
+    if missing.is_empty() { return }
+
+    let msg = format!("the '{}' task requires:  {}\n\n{}", task, missing.join(", "), matches.usage());
+    clap::Error::with_description(&msg, clap::ErrorKind::MissingRequiredArgument).exit();
 }
 
 fn parse_minutes(matches: &ArgMatches) -> Option<u64> {
@@ -111,7 +284,7 @@ fn parse_sample_rate(matches: &ArgMatches) -> Option<usize> {
 }
 
 fn parse_chunk_size(matches: &ArgMatches) -> Option<usize> {
-    if let Some(mb_arg) = matches.value_of("chunk-mb") {
+    if let Some(mb_arg) = matches.value_of("mb") {
         match mb_arg.parse::<usize>() {
             Ok(size) => Some(size),
             Err(e) => { log_err(&e); None }
@@ -120,6 +293,145 @@ fn parse_chunk_size(matches: &ArgMatches) -> Option<usize> {
     else { None }
 }
 
+fn parse_max_gap(matches: &ArgMatches) -> Option<u64> {
+    match matches.value_of("max-gap") {
+        Some(gap_arg) => match gap_arg.parse::<u64>() {
+            Ok(gap) => Some(gap),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+// an invalid chunk boundary exits, rather than falling back to chunking by size
+fn parse_slot_count(matches: &ArgMatches) -> Option<u64> {
+    matches.value_of("slots").map(|slots_arg| match slots_arg.parse::<u64>() {
+        Ok(0) => invalid_value("slots", "must be more than 0".to_string()),
+        Ok(n) => n,
+        Err(e) => invalid_value("slots", format!("'{}':  {}", slots_arg, e)),
+    })
+}
+
+fn parse_threads(matches: &ArgMatches) -> Option<usize> {
+    match matches.value_of("threads") {
+        Some(threads_arg) => match threads_arg.parse::<usize>() {
+            Ok(0) => { log_err("--threads must be more than 0"); None },
+            Ok(n) => Some(n),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+fn parse_top(matches: &ArgMatches) -> Option<usize> {
+    match matches.value_of("top") {
+        Some(top_arg) => match top_arg.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+fn parse_slot_window(matches: &ArgMatches) -> Option<u64> {
+    match matches.value_of("slot-window") {
+        Some(window_arg) => match window_arg.parse::<u64>() {
+            Ok(0) => { log_err("--slot-window must be more than 0"); None },
+            Ok(n) => Some(n),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+fn parse_votes(matches: &ArgMatches) -> VoteFilter {
+    match matches.value_of("votes") {
+        Some(name) => match VoteFilter::from_name(name) {
+            Ok(votes) => votes,
+            Err(e) => { log_err(&e); VoteFilter::Include }
+        },
+        None => VoteFilter::Include
+    }
+}
+
+fn parse_role(matches: &ArgMatches) -> Option<AccountRole> {
+    match matches.value_of("role") {
+        Some(name) => match AccountRole::from_name(name) {
+            Ok(role) => Some(role),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+fn parse_time_window(matches: &ArgMatches) -> Option<UnixTimestamp> {
+    matches.value_of("window").map(|window_arg| match parse_duration_seconds(window_arg) {
+        Ok(seconds) => seconds,
+        Err(e) => invalid_value("window", e),
+    })
+}
+
+// seconds, or a number followed by one of s, m, h, d
+fn parse_duration_seconds(input: &str) -> Result<UnixTimestamp, String> {
+    let err = || format!("time window '{}' not recognized, use e.g. 90s, 30m, 1h or 1d", input);
+    let (num, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => input.split_at(i),
+        None => (input, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1, "m" => 60, "h" => 3600, "d" => 86400,
+        _ => return Err(err())
+    };
+    match num.parse::<UnixTimestamp>() {
+        Ok(n) if n > 0 => n.checked_mul(multiplier).ok_or(format!("time window '{}' is too long", input)),
+        _ => Err(err())
+    }
+}
+
+fn parse_compression(matches: &ArgMatches) -> Compression {
+    let level = match matches.value_of("level") {
+        Some(level_arg) => match level_arg.parse::<i32>() {
+            Ok(l) => Some(l),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    };
+    match matches.value_of("compression") {
+        Some(name) => match Compression::from_name(name, level) {
+            Ok(c) => c,
+            Err(e) => { log_err(&e); Compression::None }
+        },
+        None => Compression::None
+    }
+}
+
+fn parse_chunk_format(matches: &ArgMatches) -> Option<ChunkFormat> {
+    match matches.value_of("format") {
+        Some(name) => match ChunkFormat::from_name(name) {
+            Ok(f) => Some(f),
+            Err(e) => log_err_none(&e)
+        },
+        None => None
+    }
+}
+
+fn invalid_value(name: &str, e: String) -> ! {
+    clap::Error::with_description(&format!("--{}:  {}", name, e), clap::ErrorKind::InvalidValue).exit()
+}
+
+// an invalid bound exits, rather than leaving that end of the range open & analyzing everything
+fn parse_slot_range(matches: &ArgMatches) -> SlotRange {
+    let slot = |name: &str| matches.value_of(name).map(|slot_arg| match slot_arg.parse::<u64>() {
+        Ok(s) => s,
+        Err(e) => invalid_value(name, format!("slot '{}':  {}", slot_arg, e)),
+    });
+    let time = |name: &str| matches.value_of(name).map(|time_arg| match parse_timestamp(time_arg) {
+        Ok(t) => t,
+        Err(e) => invalid_value(name, e),
+    });
+    SlotRange { from_slot: slot("from-slot"), to_slot: slot("to-slot"), since: time("since"), until: time("until") }
+}
+
 fn parse_source(matches: &ArgMatches) -> Option<String> {
     as_string_opt(matches.value_of("source"))
 }
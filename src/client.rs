@@ -1,11 +1,11 @@
-use std::{collections::{HashSet}, sync::Arc, path::Path};
+use std::{collections::{HashSet}, sync::Arc};
 
 use solana_client::{self, rpc_client::RpcClient, client_error::{ClientError}, rpc_response::RpcBlockProduction};
 use solana_program::{pubkey::Pubkey, clock::Slot};
 use solana_sdk::{transaction::Transaction, account::Account};
 use solana_transaction_status::{EncodedTransactionWithStatusMeta, UiTransactionEncoding, EncodedConfirmedBlock};
 
-use crate::{files::{slot_json_path}, networks::DEVNET_RPC, util::log_err};
+use crate::{files::{slot_file_exists}, networks::DEVNET_RPC, util::log_err};
 
 
 // TODO - basic comments explaining why the rpc wrapper etc
@@ -76,13 +76,13 @@ impl SolClient {
         });
     }
 
-    pub fn get_block_details(&mut self,
+    pub fn get_block_details<F: Fn(&(Slot, Option<&EncodedConfirmedBlock>))>(&mut self,
         slots: &Vec<Slot>,
-        callback: fn(&(Slot, Option<&EncodedConfirmedBlock>)))
+        callback: F)
         -> Slot 
     {
         for s in slots {
-            if Path::new(&slot_json_path(*s)).exists() { 
+            if slot_file_exists(*s) { 
                 println!("skipping request for slot {}: file exists", s);
                 continue; 
             }
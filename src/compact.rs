@@ -0,0 +1,159 @@
+use std::{fs, io, ops::Add, path::{Path, PathBuf}};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use solana_program::hash::{hash, Hash};
+use solana_transaction_status::EncodedConfirmedBlock;
+
+use crate::{
+    cli::CliArguments,
+    files::{
+        BLOCKS_DIR, CHUNKED_BLOCKS_DIR, existing_slot_files, get_file_size, load_block_json,
+        slot_file_extension, slot_file_name, slot_shard_dir
+    },
+    manifest::{ChunkEntry, ChunkManifest, load_verified_chunk},
+    util::{log_err, timer, do_or_die, MEGABYTE}
+};
+
+
+// where compacted slot files go
+pub(crate) struct CompactSettings<'a> {
+    pub archive_dir: Option<&'a str>,       // moved here, instead of deleted
+    pub dry_run: bool,                      // only report what would be reclaimed
+}
+
+// what compacting a chunk dir did, or would do on a dry run
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct CompactReport {
+    pub chunks_verified: usize,
+    pub chunks_skipped: usize,          // failed verification, their slot files are kept
+    pub files_compacted: usize,
+    pub bytes_reclaimed: usize,
+    pub files_kept: usize,              // unreadable, or holding a different block than the chunk
+}
+
+impl Add for CompactReport {
+    type Output = CompactReport;
+
+    fn add(self, other: CompactReport) -> CompactReport {
+        CompactReport {
+            chunks_verified: self.chunks_verified + other.chunks_verified,
+            chunks_skipped: self.chunks_skipped + other.chunks_skipped,
+            files_compacted: self.files_compacted + other.files_compacted,
+            bytes_reclaimed: self.bytes_reclaimed + other.bytes_reclaimed,
+            files_kept: self.files_kept + other.files_kept,
+        }
+    }
+}
+
+// the block's json, hashed, so blocks from slot files & chunks can be compared
+fn block_checksum(block: &EncodedConfirmedBlock) -> Option<Hash> {
+    serde_json::to_vec(block).map(|json| hash(&json)).ok()
+}
+
+// remove the raw slot files for every chunk in the chunk dir's manifest.
+// a chunk is only trusted once it's re-read & matches its manifest entry's checksum & block count,
+// & a slot file is only removed if it holds the same block as the chunk does.
+// chunks without a manifest entry have nothing to check against, so their slot files are left alone
+pub(crate) fn compact_blocks(blocks_dir: &str, chunk_dir: &str, settings: &CompactSettings) -> CompactReport {
+    let manifest = match ChunkManifest::load(chunk_dir) {
+        Ok(m) => m,
+        Err(e) => { log_err(&e); return CompactReport::default() }
+    };
+    println!("chunks in manifest:  {}", manifest.chunks.len());
+
+    // 1 chunk loaded per thread at a time
+    manifest.chunks.par_iter()
+        .map(|entry| compact_chunk(blocks_dir, chunk_dir, entry, settings))
+        .reduce(CompactReport::default, |a, b| a + b)
+}
+
+fn compact_chunk(blocks_dir: &str, chunk_dir: &str, entry: &ChunkEntry, settings: &CompactSettings)
+    -> CompactReport
+{
+    let chunk = match load_verified_chunk(chunk_dir, entry) {
+        Ok(chunk) => chunk,
+        Err(problem) => {
+            log_err(&format!("{}:  {:?},  keeping its slot files", entry.file, problem));
+            return CompactReport { chunks_skipped: 1, ..Default::default() }
+        }
+    };
+
+    let mut report = CompactReport { chunks_verified: 1, ..Default::default() };
+    for (slot, block) in chunk.iter() {
+        let checksum = block_checksum(block);
+        for path in existing_slot_files(blocks_dir, *slot) {
+            let matches = checksum.is_some() && load_block_json(&path).and_then(|b| block_checksum(&b)) == checksum;
+            if !matches {
+                log_err(&format!("{:?} doesn't match slot {} in {},  keeping it", path, slot, entry.file));
+                report.files_kept += 1;
+                continue;
+            }
+
+            let bytes = get_file_size(&path);
+            match reclaim_slot_file(&path, *slot, settings) {
+                Ok(_) => {
+                    report.files_compacted += 1;
+                    report.bytes_reclaimed += bytes;
+                },
+                Err(e) => { log_err(&e); report.files_kept += 1 }
+            }
+        }
+    }
+    report
+}
+
+fn reclaim_slot_file(path: &Path, slot: u64, settings: &CompactSettings) -> io::Result<()> {
+    if settings.dry_run { return Ok(()) }
+
+    match settings.archive_dir {
+        Some(archive_dir) => {
+            // archived files keep the sharded layout, so they can be moved back as-is
+            let ext = slot_file_extension(path).unwrap_or_default();
+            fs::create_dir_all(slot_shard_dir(archive_dir, slot))?;
+            let dest = PathBuf::from(slot_file_name(archive_dir, slot, &ext));
+            if dest.exists() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{:?} already archived", dest)))
+            }
+            // rename can't cross filesystems, so fall back to copying
+            fs::rename(path, &dest).or_else(|_| fs::copy(path, &dest).and_then(|_| fs::remove_file(path)))
+        },
+        None => fs::remove_file(path),
+    }
+}
+
+const NO_SRC_EXIT_MSG: &str = "can't proceed without a valid chunk directory!\nexiting\n";
+
+// handler for the 'compact_blocks' CLI task
+pub(crate) fn compact_blocks_cli(args: &CliArguments) {
+    let chunk_dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    do_or_die(|| fs::metadata(chunk_dir), NO_SRC_EXIT_MSG);
+
+    let archive_dir = args.archive.as_deref();
+    if let Some(dir) = archive_dir {
+        if let Err(e) = fs::create_dir_all(dir) { return log_err(&e) }
+        // archived files inside the blocks dir would just be found again
+        if let (Ok(archive), Ok(blocks)) = (fs::canonicalize(dir), fs::canonicalize(BLOCKS_DIR)) {
+            if archive.starts_with(&blocks) {
+                return log_err(&format!("archive dir {} can't be inside {}", dir, BLOCKS_DIR));
+            }
+        }
+    }
+
+    let settings = CompactSettings { archive_dir, dry_run: args.dry_run };
+    let action = match (settings.dry_run, archive_dir) {
+        (true, _) => "dry run, nothing will be removed".to_string(),
+        (false, Some(dir)) => format!("moving them to {}", dir),
+        (false, None) => "deleting them".to_string(),
+    };
+    println!("\ncompacting slot files in {} that are chunked in {},  {}", BLOCKS_DIR, chunk_dir, action);
+
+    let elapsed = timer(|| {
+        let report = compact_blocks(BLOCKS_DIR, chunk_dir, &settings);
+        let verb = if settings.dry_run { "would be" } else { "were" };
+        println!("chunks verified:  {},  skipped:  {}", report.chunks_verified, report.chunks_skipped);
+        println!("{} slot files {} compacted,  {:.2} MB reclaimed",
+                 report.files_compacted, verb, report.bytes_reclaimed as f64 / MEGABYTE as f64);
+        if report.files_kept > 0 { println!("slot files kept:  {}", report.files_kept) }
+    });
+    println!("done, time:  {:3} seconds", elapsed.as_secs_f32());
+}
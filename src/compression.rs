@@ -0,0 +1,121 @@
+use std::{fs::{self, File}, io::{self, Read, Write, BufReader, BufWriter}, path::Path};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+
+pub(crate) const ZSTD_EXTENSION: &str = ".zst";
+pub(crate) const GZIP_EXTENSION: &str = ".gz";
+pub(crate) const COMPRESSION_EXTENSIONS: [&str; 3] = ["", ZSTD_EXTENSION, GZIP_EXTENSION];
+
+pub(crate) const ZSTD_NAME: &str = "zstd";
+pub(crate) const GZIP_NAME: &str = "gzip";
+pub(crate) const NO_COMPRESSION_NAME: &str = "none";
+
+pub(crate) const DEFAULT_ZSTD_LEVEL: i32 = 3;
+pub(crate) const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+// how a block or chunk file's bytes are stored on disk.
+// which one a file uses is always decided by its extension, e.g. "slot_*.json.zst"
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Compression {
+    #[default]
+    None,
+    Zstd(i32),
+    Gzip(u32),
+}
+
+impl Compression {
+    // the extension appended after the data format's own, like ".json"
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Zstd(_) => ZSTD_EXTENSION,
+            Compression::Gzip(_) => GZIP_EXTENSION,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => NO_COMPRESSION_NAME,
+            Compression::Zstd(_) => ZSTD_NAME,
+            Compression::Gzip(_) => GZIP_NAME,
+        }
+    }
+
+    // level doesn't matter for reading, so files get the default one
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        let name = path.as_ref().to_string_lossy();
+        if name.ends_with(ZSTD_EXTENSION) { Compression::Zstd(DEFAULT_ZSTD_LEVEL) }
+        else if name.ends_with(GZIP_EXTENSION) { Compression::Gzip(DEFAULT_GZIP_LEVEL) }
+        else { Compression::None }
+    }
+
+    // parse a --compression name & optional --level, out of range levels are clamped
+    pub fn from_name(name: &str, level: Option<i32>) -> Result<Compression, String> {
+        match name.to_lowercase().as_str() {
+            ZSTD_NAME | "zst" =>
+                Ok(Compression::Zstd(level.unwrap_or(DEFAULT_ZSTD_LEVEL).clamp(1, 21))),
+            GZIP_NAME | "gz" =>
+                Ok(Compression::Gzip(level.map_or(DEFAULT_GZIP_LEVEL, |l| l.clamp(0, 9) as u32))),
+            NO_COMPRESSION_NAME => Ok(Compression::None),
+            other => Err(format!("compression '{}' not recognized, use one of: {}, {}, {}",
+                                 other, ZSTD_NAME, GZIP_NAME, NO_COMPRESSION_NAME))
+        }
+    }
+}
+
+// open a file for reading, decompressing on the fly according to its extension
+pub(crate) fn open_reader<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(&path)?);
+    Ok(match Compression::from_path(&path) {
+        Compression::None => Box::new(file),
+        Compression::Zstd(_) => Box::new(zstd::Decoder::new(file)?),
+        Compression::Gzip(_) => Box::new(GzDecoder::new(file)),
+    })
+}
+
+// read a whole file's decompressed contents
+pub(crate) fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    match Compression::from_path(&path) {
+        Compression::None => fs::read(path),
+        _ => {
+            let mut data = Vec::<u8>::new();
+            open_reader(path)?.read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+// write data to a file, compressed with the given settings
+pub(crate) fn write_file<P: AsRef<Path>>(path: P, data: &[u8], compression: Compression)
+    -> io::Result<()>
+{
+    match compression {
+        Compression::None => fs::write(path, data),
+        Compression::Zstd(level) => {
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = zstd::Encoder::new(file, level)?;
+            encoder.write_all(data)?;
+            encoder.finish()?.flush()
+        },
+        Compression::Gzip(level) => {
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = GzEncoder::new(file, flate2::Compression::new(level));
+            encoder.write_all(data)?;
+            encoder.finish()?.flush()
+        },
+    }
+}
+
+// compress data in memory, for when the exact bytes written to disk are needed (e.g. to checksum them)
+pub(crate) fn compress(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Zstd(level) => zstd::encode_all(data, level),
+        Compression::Gzip(level) => {
+            let mut encoder = GzEncoder::new(Vec::<u8>::new(), flate2::Compression::new(level));
+            encoder.write_all(data)?;
+            encoder.finish()
+        },
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Write, str::FromStr};
+
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    decode::core_program_name,
+    normalized::{NormalizedBlock, NormalizedInstruction, NormalizedTx}
+};
+
+
+// the programs that invoke other programs by CPI:  a directed graph from caller to invoked program,
+// built from the inner instructions, with each call's depth taken from the transaction's logs
+#[derive(Default)]
+pub(crate) struct CpiGraph {
+    top: usize,
+    edges: HashMap<(Pubkey, Pubkey), EdgeStats>,        // by caller & invoked program
+    top_level: HashMap<Pubkey, u64>,                    // programs' top-level instructions
+    // inner instructions that couldn't be matched to the logs' invokes, so they're counted as called
+    // by the top-level instruction's program, at an unknown depth
+    unresolved: u64,
+    report: Option<CpiReport>,                          // filled in by finalize()
+}
+
+#[derive(Default)]
+struct EdgeStats {
+    calls: u64,
+    depths: BTreeMap<usize, u64>,       // invoke depth -> calls, 2 for a call made by a top-level instruction
+}
+
+impl EdgeStats {
+    fn merge(&mut self, other: EdgeStats) {
+        self.calls += other.calls;
+        other.depths.into_iter().for_each(|(depth, calls)| *self.depths.entry(depth).or_insert(0) += calls);
+    }
+}
+
+#[derive(Serialize)]
+struct CpiReport {
+    programs: usize,
+    cpi_calls: u64,
+    unresolved: u64,
+    edges: Vec<EdgeRow>,
+    by_program: Vec<ProgramCalls>,
+}
+
+#[derive(Serialize)]
+struct EdgeRow {
+    caller: String,
+    callee: String,
+    calls: u64,
+    depths: BTreeMap<usize, u64>,
+}
+
+// one program's place in the graph:  what calls it & what it calls
+#[derive(Serialize)]
+struct ProgramCalls {
+    program: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+    top_level: u64,
+    called: u64,
+    calls: u64,
+    callers: Vec<ProgramCount>,
+    callees: Vec<ProgramCount>,
+}
+
+#[derive(Serialize, Clone)]
+struct ProgramCount {
+    program: String,
+    calls: u64,
+}
+
+// what each top-level instruction invoked, in order, from the invoke lines in its logs:
+// (invoked program, the program that invoked it, depth)
+fn logged_invokes(tx: &NormalizedTx) -> Vec<Vec<(Pubkey, Option<Pubkey>, usize)>> {
+    let mut invokes = Vec::<Vec<(Pubkey, Option<Pubkey>, usize)>>::new();
+    for log in tx.invoke_logs() {
+        match (log.invoke, log.program) {
+            (Some((_, depth)), _) if depth <= 1 => invokes.push(vec![]),
+            (Some((caller, depth)), Some(id)) => if let Some(group) = invokes.last_mut() { group.push((id, caller, depth)) },
+            _ => {},
+        }
+    }
+    invokes
+}
+
+fn label(program: &Pubkey) -> String {
+    match core_program_name(program) {
+        Some(name) => format!("{}\\n{}", name, program),
+        None => program.to_string(),
+    }
+}
+
+impl CpiGraph {
+    pub fn new(top: usize) -> CpiGraph {
+        CpiGraph { top, ..Default::default() }
+    }
+
+    fn call(&mut self, caller: Pubkey, callee: Pubkey, depth: Option<usize>) {
+        let edge = self.edges.entry((caller, callee)).or_default();
+        edge.calls += 1;
+        match depth {
+            Some(depth) => *edge.depths.entry(depth).or_insert(0) += 1,
+            None => self.unresolved += 1,
+        }
+    }
+
+    // the calls an instruction's inner instructions made:  from the logs when they line up with the inner instructions,
+    // which they don't when the logs were truncated, or else all from the top-level program
+    fn inner_calls(&mut self, top_level: &NormalizedInstruction, inner: &[NormalizedInstruction],
+        logged: Option<&Vec<(Pubkey, Option<Pubkey>, usize)>>)
+    {
+        let matched = logged.filter(|logged| {
+            logged.len() == inner.len() && logged.iter().zip(inner).all(|((id, caller, _), ix)| *id == ix.program_id && caller.is_some())
+        });
+        match matched {
+            Some(logged) => logged.iter().for_each(|(callee, caller, depth)| {
+                self.call(caller.unwrap_or(top_level.program_id), *callee, Some(*depth))
+            }),
+            None => inner.iter().for_each(|ix| self.call(top_level.program_id, ix.program_id, None)),
+        }
+    }
+
+    fn dot(report: &CpiReport) -> String {
+        let mut dot = String::from("digraph cpi {\n    rankdir=LR;\n    node [shape=box];\n");
+        report.by_program.iter().for_each(|p| {
+            let program = Pubkey::from_str(&p.program).map(|k| label(&k)).unwrap_or_else(|_| p.program.clone());
+            let _ = writeln!(dot, "    \"{}\" [label=\"{}\"];", p.program, program);
+        });
+        report.edges.iter().for_each(|e| {
+            let _ = writeln!(dot, "    \"{}\" -> \"{}\" [label=\"{}\", weight={}];", e.caller, e.callee, e.calls, e.calls);
+        });
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn graphml(report: &CpiReport) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+            "  <key id=\"top_level\" for=\"node\" attr.name=\"top_level\" attr.type=\"long\"/>\n",
+            "  <key id=\"calls\" for=\"edge\" attr.name=\"calls\" attr.type=\"long\"/>\n",
+            "  <key id=\"max_depth\" for=\"edge\" attr.name=\"max_depth\" attr.type=\"int\"/>\n",
+            "  <graph id=\"cpi\" edgedefault=\"directed\">\n"));
+        report.by_program.iter().for_each(|p| {
+            let _ = write!(xml, "    <node id=\"{}\">", p.program);
+            if let Some(name) = p.name { let _ = write!(xml, "<data key=\"name\">{}</data>", name); }
+            let _ = writeln!(xml, "<data key=\"top_level\">{}</data></node>", p.top_level);
+        });
+        report.edges.iter().enumerate().for_each(|(i, e)| {
+            let _ = write!(xml, "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"><data key=\"calls\">{}</data>", i, e.caller, e.callee, e.calls);
+            if let Some(depth) = e.depths.keys().max() { let _ = write!(xml, "<data key=\"max_depth\">{}</data>", depth); }
+            xml.push_str("</edge>\n");
+        });
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+// most calls first
+fn sorted_counts(counts: HashMap<Pubkey, u64>) -> Vec<ProgramCount> {
+    let mut sorted: Vec<ProgramCount> = counts.into_iter()
+        .map(|(program, calls)| ProgramCount { program: program.to_string(), calls })
+        .collect();
+    ranked(&mut sorted, |c| c.calls, |a, b| a.program.cmp(&b.program));
+    sorted
+}
+
+impl Analyzer for CpiGraph {
+    fn empty(&self) -> CpiGraph {
+        CpiGraph::new(self.top)
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, tx: &NormalizedTx) {
+        tx.instructions.iter().for_each(|ix| *self.top_level.entry(ix.program_id).or_insert(0) += 1);
+        if tx.inner_instructions.is_empty() { return }
+
+        let logged = logged_invokes(tx);
+        // the logs only line up with the instructions if every top-level instruction logged its invoke
+        let logged = if logged.len() == tx.instructions.len() { logged } else { vec![] };
+        for inner in tx.inner_instructions.iter() {
+            let top_level = match tx.instructions.get(inner.index as usize) {
+                Some(ix) => ix,
+                None => continue,
+            };
+            self.inner_calls(top_level, &inner.instructions, logged.get(inner.index as usize));
+        }
+    }
+
+    fn merge(&mut self, other: CpiGraph) {
+        other.edges.into_iter().for_each(|(key, stats)| self.edges.entry(key).or_default().merge(stats));
+        other.top_level.into_iter().for_each(|(program, count)| *self.top_level.entry(program).or_insert(0) += count);
+        self.unresolved += other.unresolved;
+    }
+
+    fn finalize(&mut self) {
+        let mut edges: Vec<EdgeRow> = self.edges.iter().map(|((caller, callee), stats)| EdgeRow {
+            caller: caller.to_string(),
+            callee: callee.to_string(),
+            calls: stats.calls,
+            depths: stats.depths.clone(),
+        }).collect();
+        ranked(&mut edges, |e| e.calls, |a, b| a.caller.cmp(&b.caller).then(a.callee.cmp(&b.callee)));
+
+        // only programs that call or are called are in the graph, not those only ever invoked at the top level
+        let mut callers = HashMap::<Pubkey, HashMap<Pubkey, u64>>::new();
+        let mut callees = HashMap::<Pubkey, HashMap<Pubkey, u64>>::new();
+        self.edges.iter().for_each(|((caller, callee), stats)| {
+            callers.entry(*callee).or_default().insert(*caller, stats.calls);
+            callees.entry(*caller).or_default().insert(*callee, stats.calls);
+            callers.entry(*caller).or_default();
+            callees.entry(*callee).or_default();
+        });
+        let mut by_program: Vec<ProgramCalls> = callers.into_iter().map(|(program, program_callers)| {
+            let program_callees = callees.remove(&program).unwrap_or_default();
+            ProgramCalls {
+                program: program.to_string(),
+                name: core_program_name(&program),
+                top_level: self.top_level.get(&program).copied().unwrap_or(0),
+                called: program_callers.values().sum(),
+                calls: program_callees.values().sum(),
+                callers: sorted_counts(program_callers),
+                callees: sorted_counts(program_callees),
+            }
+        }).collect();
+        ranked(&mut by_program, |p| p.called + p.calls, |a, b| a.program.cmp(&b.program));
+
+        self.report = Some(CpiReport {
+            programs: by_program.len(),
+            cpi_calls: edges.iter().map(|e| e.calls).sum(),
+            unresolved: self.unresolved,
+            edges,
+            by_program,
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        println!("programs in the call graph:  {},  calls between programs:  {},  CPI calls:  {}",
+                 report.programs, report.edges.len(), report.cpi_calls);
+        if report.unresolved > 0 {
+            println!("{} CPI calls didn't match their transaction's logs, & are counted as made by the top-level program", report.unresolved);
+        }
+
+        println!("\n    {:<44}    {:<44} {:>10} {:>8}", "caller", "callee", "calls", "depths");
+        report.edges.iter().take(self.top).for_each(|e| {
+            let depths = e.depths.keys().map(|d| d.to_string()).collect::<Vec<_>>().join(",");
+            println!("    {:<44} -> {:<44} {:>10} {:>8}", e.caller, e.callee, e.calls, depths);
+        });
+
+        println!("\n    {:<44} {:>10} {:>10} {:>10}", "program", "top-level", "called", "calls");
+        report.by_program.iter().take(self.top).for_each(|p| {
+            println!("    {:<44} {:>10} {:>10} {:>10}", p.program, p.top_level, p.called, p.calls);
+            let list = |counts: &[ProgramCount]| counts.iter().take(3)
+                .map(|c| format!("{} x{}", c.program, c.calls)).collect::<Vec<_>>().join(",  ");
+            if !p.callers.is_empty() { println!("        called by  {}", list(&p.callers)) }
+            if !p.callees.is_empty() { println!("        calls      {}", list(&p.callees)) }
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        out.write_json("", report)?;
+        out.write_text(".dot", &CpiGraph::dot(report))?;
+        out.write_text(".graphml", &CpiGraph::graphml(report))
+    }
+}
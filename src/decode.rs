@@ -0,0 +1,261 @@
+use std::fmt::{self, Display, Formatter};
+
+use solana_program::{
+    pubkey::Pubkey, system_instruction::SystemInstruction, system_program,
+    stake::{self, instruction::StakeInstruction}
+};
+use solana_sdk::{program_utils::limited_deserialize, vote};
+use solana_vote_program::vote_instruction::VoteInstruction;
+use spl_token::instruction::TokenInstruction;
+
+use crate::util::{snake_case, variant_name};
+
+
+// an instruction to one of the core programs, decoded from its data into that program's own instruction type
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum DecodedInstruction {
+    System(SystemInstruction),
+    Token(TokenInstruction),
+    AssociatedToken(AssociatedTokenInstruction),
+    Memo(String),
+    Stake(StakeInstruction),
+    Vote(VoteInstruction),
+}
+
+// the associated token account program started out with one instruction & no data,
+// later versions lead with a 1 byte tag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AssociatedTokenInstruction {
+    Create,
+    CreateIdempotent,
+    RecoverNested,
+}
+
+// the programs with a decoder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CoreProgram {
+    System,
+    Token,
+    AssociatedToken,
+    Memo,
+    Stake,
+    Vote,
+}
+
+impl CoreProgram {
+    pub fn of(program_id: &Pubkey) -> Option<CoreProgram> {
+        match program_id {
+            p if system_program::check_id(p) => Some(CoreProgram::System),
+            p if spl_token::check_id(p) => Some(CoreProgram::Token),
+            p if spl_associated_token_account::check_id(p) => Some(CoreProgram::AssociatedToken),
+            p if spl_memo::check_id(p) || spl_memo::v1::check_id(p) => Some(CoreProgram::Memo),
+            p if stake::program::check_id(p) => Some(CoreProgram::Stake),
+            p if vote::program::check_id(p) => Some(CoreProgram::Vote),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CoreProgram::System => "system",
+            CoreProgram::Token => "spl_token",
+            CoreProgram::AssociatedToken => "associated_token",
+            CoreProgram::Memo => "memo",
+            CoreProgram::Stake => "stake",
+            CoreProgram::Vote => "vote",
+        }
+    }
+}
+
+// the name of a program with a decoder, or None for any other program
+pub(crate) fn core_program_name(program_id: &Pubkey) -> Option<&'static str> {
+    CoreProgram::of(program_id).map(|p| p.name())
+}
+
+// Ok(None) for programs without a decoder, Err for data their decoder can't make sense of
+pub(crate) fn decode_instruction(program_id: &Pubkey, data: &[u8]) -> Result<Option<DecodedInstruction>, String> {
+    let program = match CoreProgram::of(program_id) {
+        Some(program) => program,
+        None => return Ok(None),
+    };
+    let decoded = match program {
+        CoreProgram::System => limited_deserialize(data).map(DecodedInstruction::System).map_err(|e| e.to_string()),
+        CoreProgram::Token => TokenInstruction::unpack(data).map(DecodedInstruction::Token).map_err(|e| e.to_string()),
+        CoreProgram::AssociatedToken => match data.first() {
+            None | Some(0) => Ok(AssociatedTokenInstruction::Create),
+            Some(1) => Ok(AssociatedTokenInstruction::CreateIdempotent),
+            Some(2) => Ok(AssociatedTokenInstruction::RecoverNested),
+            Some(tag) => Err(format!("unknown instruction tag {}", tag)),
+        }.map(DecodedInstruction::AssociatedToken),
+        CoreProgram::Memo => String::from_utf8(data.to_vec()).map(DecodedInstruction::Memo).map_err(|e| e.to_string()),
+        CoreProgram::Stake => limited_deserialize(data).map(DecodedInstruction::Stake).map_err(|e| e.to_string()),
+        CoreProgram::Vote => limited_deserialize(data).map(DecodedInstruction::Vote).map_err(|e| e.to_string()),
+    };
+    decoded.map(Some).map_err(|e| format!("can't decode {} instruction:  {}", program.name(), e))
+}
+
+impl DecodedInstruction {
+    pub fn program(&self) -> CoreProgram {
+        match self {
+            DecodedInstruction::System(_) => CoreProgram::System,
+            DecodedInstruction::Token(_) => CoreProgram::Token,
+            DecodedInstruction::AssociatedToken(_) => CoreProgram::AssociatedToken,
+            DecodedInstruction::Memo(_) => CoreProgram::Memo,
+            DecodedInstruction::Stake(_) => CoreProgram::Stake,
+            DecodedInstruction::Vote(_) => CoreProgram::Vote,
+        }
+    }
+
+    // e.g. "system::transfer" or "spl_token::mint_to"
+    pub fn name(&self) -> String {
+        let instruction = match self {
+            DecodedInstruction::System(ix) => variant_name(ix),
+            DecodedInstruction::Token(ix) => variant_name(ix),
+            DecodedInstruction::AssociatedToken(ix) => variant_name(ix),
+            DecodedInstruction::Memo(_) => "memo".to_string(),
+            DecodedInstruction::Stake(ix) => variant_name(ix),
+            DecodedInstruction::Vote(ix) => variant_name(ix),
+        };
+        format!("{}::{}", self.program().name(), snake_case(&instruction))
+    }
+
+    // what the instruction's accounts are, in order, for the more common instructions.
+    // empty where they aren't named, & instructions can have more accounts than are named (e.g. multisig signers)
+    pub fn account_names(&self) -> &'static [&'static str] {
+        use SystemInstruction as Sys;
+        use TokenInstruction as Tok;
+        match self {
+            DecodedInstruction::System(ix) => match ix {
+                Sys::CreateAccount { .. } => &["funder", "new_account"],
+                Sys::CreateAccountWithSeed { .. } => &["funder", "new_account", "base"],
+                Sys::Assign { .. } => &["account"],
+                Sys::AssignWithSeed { .. } => &["account", "base"],
+                Sys::Transfer { .. } => &["from", "to"],
+                Sys::TransferWithSeed { .. } => &["from", "base", "to"],
+                Sys::Allocate { .. } => &["account"],
+                Sys::AllocateWithSeed { .. } => &["account", "base"],
+                Sys::AdvanceNonceAccount => &["nonce_account", "recent_blockhashes", "nonce_authority"],
+                Sys::WithdrawNonceAccount(_) => &["nonce_account", "to", "recent_blockhashes", "rent", "nonce_authority"],
+                Sys::InitializeNonceAccount(_) => &["nonce_account", "recent_blockhashes", "rent"],
+                Sys::AuthorizeNonceAccount(_) => &["nonce_account", "nonce_authority"],
+            },
+            DecodedInstruction::Token(ix) => match ix {
+                Tok::InitializeMint { .. } => &["mint", "rent"],
+                Tok::InitializeAccount => &["account", "mint", "owner", "rent"],
+                Tok::InitializeAccount2 { .. } => &["account", "mint", "rent"],
+                Tok::InitializeMultisig { .. } => &["multisig", "rent"],
+                Tok::Transfer { .. } => &["source", "destination", "owner"],
+                Tok::TransferChecked { .. } => &["source", "mint", "destination", "owner"],
+                Tok::Approve { .. } => &["source", "delegate", "owner"],
+                Tok::ApproveChecked { .. } => &["source", "mint", "delegate", "owner"],
+                Tok::Revoke => &["source", "owner"],
+                Tok::SetAuthority { .. } => &["account", "authority"],
+                Tok::MintTo { .. } | Tok::MintToChecked { .. } => &["mint", "account", "mint_authority"],
+                Tok::Burn { .. } | Tok::BurnChecked { .. } => &["account", "mint", "owner"],
+                Tok::CloseAccount => &["account", "destination", "owner"],
+                Tok::FreezeAccount | Tok::ThawAccount => &["account", "mint", "freeze_authority"],
+                Tok::SyncNative => &["account"],
+            },
+            DecodedInstruction::AssociatedToken(AssociatedTokenInstruction::RecoverNested) =>
+                &["nested_account", "nested_mint", "destination", "owner_account", "owner_mint", "wallet"],
+            DecodedInstruction::AssociatedToken(_) =>
+                &["funder", "associated_account", "wallet", "mint", "system_program", "token_program"],
+            DecodedInstruction::Memo(_) => &[],
+            DecodedInstruction::Stake(ix) => match ix {
+                StakeInstruction::Initialize(..) => &["stake", "rent"],
+                StakeInstruction::DelegateStake => &["stake", "vote", "clock", "stake_history", "config", "stake_authority"],
+                StakeInstruction::Split(_) => &["stake", "split_stake", "stake_authority"],
+                StakeInstruction::Withdraw(_) => &["stake", "to", "clock", "stake_history", "withdraw_authority"],
+                StakeInstruction::Deactivate => &["stake", "clock", "stake_authority"],
+                StakeInstruction::Merge => &["destination_stake", "source_stake", "clock", "stake_history", "stake_authority"],
+                _ => &[],
+            },
+            DecodedInstruction::Vote(ix) => match ix {
+                VoteInstruction::Vote(_) | VoteInstruction::VoteSwitch(..) =>
+                    &["vote_account", "slot_hashes", "clock", "vote_authority"],
+                VoteInstruction::Withdraw(_) => &["vote_account", "to", "withdraw_authority"],
+                _ => &[],
+            },
+        }
+    }
+}
+
+// the decoded instruction's own fields, e.g. "Transfer { lamports: 5000 }"
+impl Display for DecodedInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedInstruction::System(ix) => write!(f, "{:?}", ix),
+            DecodedInstruction::Token(ix) => write!(f, "{:?}", ix),
+            DecodedInstruction::AssociatedToken(ix) => write!(f, "{:?}", ix),
+            DecodedInstruction::Memo(memo) => write!(f, "{:?}", memo),
+            DecodedInstruction::Stake(ix) => write!(f, "{:?}", ix),
+            DecodedInstruction::Vote(ix) => write!(f, "{:?}", ix),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::system_instruction;
+
+    fn decoded(program_id: &Pubkey, data: &[u8]) -> DecodedInstruction {
+        decode_instruction(program_id, data).unwrap().unwrap()
+    }
+
+    #[test]
+    fn other_programs_have_no_decoder() {
+        assert_eq!(decode_instruction(&Pubkey::new_unique(), &[1, 2, 3]), Ok(None));
+    }
+
+    #[test]
+    fn system_transfer() {
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = system_instruction::transfer(&from, &to, 5000);
+        let decoded = decoded(&ix.program_id, &ix.data);
+
+        assert_eq!(decoded, DecodedInstruction::System(SystemInstruction::Transfer { lamports: 5000 }));
+        assert_eq!(decoded.name(), "system::transfer");
+        assert_eq!(decoded.account_names(), &["from", "to"]);
+    }
+
+    #[test]
+    fn token_transfer_checked() {
+        let keys: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let ix = spl_token::instruction::transfer_checked(&spl_token::id(), &keys[0], &keys[1], &keys[2], &keys[3], &[], 250, 6)
+            .unwrap();
+        let decoded = decoded(&ix.program_id, &ix.data);
+
+        assert_eq!(decoded, DecodedInstruction::Token(TokenInstruction::TransferChecked { amount: 250, decimals: 6 }));
+        assert_eq!(decoded.name(), "spl_token::transfer_checked");
+        assert_eq!(decoded.account_names(), &["source", "mint", "destination", "owner"]);
+    }
+
+    #[test]
+    fn associated_token_with_empty_and_tagged_data() {
+        let program_id = spl_associated_token_account::id();
+        let create = DecodedInstruction::AssociatedToken(AssociatedTokenInstruction::Create);
+
+        assert_eq!(decoded(&program_id, &[]), create);
+        assert_eq!(decoded(&program_id, &[0]), create);
+        assert_eq!(decoded(&program_id, &[1]).name(), "associated_token::create_idempotent");
+        assert_eq!(decoded(&program_id, &[2]), DecodedInstruction::AssociatedToken(AssociatedTokenInstruction::RecoverNested));
+        assert!(decode_instruction(&program_id, &[3]).is_err());
+    }
+
+    #[test]
+    fn memo_v1_and_v2() {
+        let memo = DecodedInstruction::Memo("gm".to_string());
+
+        assert_eq!(decoded(&spl_memo::id(), b"gm"), memo);
+        assert_eq!(decoded(&spl_memo::v1::id(), b"gm"), memo);
+        assert_eq!(memo.name(), "memo::memo");
+        assert!(decode_instruction(&spl_memo::id(), &[0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn bad_data_names_the_program() {
+        let err = decode_instruction(&system_program::id(), &[9, 9]).unwrap_err();
+        assert!(err.starts_with("can't decode system instruction"), "{}", err);
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{
+    cli::CliArguments,
+    normalized::{NormalizedBlock, NormalizedInstruction, NormalizedTx, normalize_block},
+    files::{BLOCKS_DIR, CHUNKED_BLOCKS_DIR, existing_slot_files, chunk_file_paths, load_block_json},
+    chunk_stream::stream_normalized_chunk_in_range,
+    slot_range::{SlotRange, select_chunk_paths},
+    anchor_idl::{IdlRegistry, load_idl_args},
+    util::log_err
+};
+
+
+// a slot's block, from its slot file if the dir has one, or else from whichever of the dir's chunks holds it
+fn find_block(dir: &str, slot: u64) -> Result<Option<NormalizedBlock>, String> {
+    if let Some(path) = existing_slot_files(dir, slot).first() {
+        return match load_block_json(path) {
+            Some(block) => normalize_block(slot, &block).map(Some),
+            None => Err(format!("can't load {}", path.to_string_lossy())),
+        };
+    }
+    if chunk_file_paths(dir).is_empty() { return Ok(None) }
+
+    let range = SlotRange { from_slot: Some(slot), to_slot: Some(slot), ..Default::default() };
+    for path in select_chunk_paths(dir, &range) {
+        for block in stream_normalized_chunk_in_range(&path, &range)? {
+            let block = block.map_err(|e| format!("{}:  {}", path.to_string_lossy(), e))?;
+            if block.slot == slot { return Ok(Some(block)) }
+        }
+    }
+    Ok(None)
+}
+
+// "name: value, ..." for an Anchor instruction's args or event's fields
+fn named_values(values: &[(&str, Value)]) -> String {
+    values.iter().map(|(name, value)| format!("{}: {}", name, value)).collect::<Vec<_>>().join(",  ")
+}
+
+// core programs' instructions are decoded by their own decoders, others by any IDL registered for them
+fn print_instruction(tx: &NormalizedTx, ix: &NormalizedInstruction, idls: &IdlRegistry, label: &str, indent: &str) {
+    let core = match ix.decode() {
+        Ok(core) => core,
+        Err(e) => return println!("{}{:<6} {}  {}", indent, label, ix.program_id, e),
+    };
+    let anchor = match core {
+        Some(_) => None,
+        None => match idls.decode_instruction(&ix.program_id, &ix.data) {
+            Ok(anchor) => anchor,
+            Err(e) => return println!("{}{:<6} {}  {}", indent, label, ix.program_id, e),
+        },
+    };
+    let names: Vec<String> = match (&core, &anchor) {
+        (Some(decoded), _) => {
+            println!("{}{:<6} {}  {}", indent, label, decoded.name(), decoded);
+            decoded.account_names().iter().map(|n| n.to_string()).collect()
+        },
+        (None, Some(decoded)) => {
+            println!("{}{:<6} {}::{}  {}", indent, label, decoded.program, decoded.name, named_values(&decoded.args));
+            decoded.account_names.to_vec()
+        },
+        (None, None) =>
+            return println!("{}{:<6} {}  data:  {}", indent, label, ix.program_id, bs58::encode(&ix.data).into_string()),
+    };
+
+    // unnamed accounts, like multisig signers, are listed by position
+    ix.accounts.iter().enumerate().for_each(|(i, index)| {
+        let name = names.get(i).map(|n| n.to_string()).unwrap_or_else(|| format!("account {}", i));
+        match tx.accounts.get(*index as usize) {
+            Some(acct) => println!("{}       {:<22} {}", indent, name, acct.pubkey),
+            None => println!("{}       {:<22} missing account index {}", indent, name, index),
+        }
+    });
+}
+
+fn print_tx(tx: &NormalizedTx, tx_index: usize, idls: &IdlRegistry) {
+    let signature = tx.signatures.first().map(|s| s.to_string()).unwrap_or_default();
+    println!("\ntx {}  {}", tx_index, signature);
+    let fee_payer = tx.fee_payer().map(|p| p.to_string()).unwrap_or_default();
+    match &tx.meta {
+        Some(meta) => {
+            let status = match &meta.status {
+                Ok(()) => "ok".to_string(),
+                Err(e) => format!("failed:  {}", e),
+            };
+            println!("    fee payer:  {},  fee:  {},  status:  {}", fee_payer, meta.fee, status);
+        },
+        None => println!("    fee payer:  {},  no status meta", fee_payer),
+    }
+
+    tx.instructions.iter().enumerate().for_each(|(i, ix)| {
+        print_instruction(tx, ix, idls, &format!("#{}", i), "    ");
+        // inner instructions are grouped by the top-level instruction that invoked them
+        tx.inner_instructions.iter().filter(|inner| inner.index as usize == i).for_each(|inner| {
+            inner.instructions.iter().enumerate().for_each(|(j, inner_ix)| {
+                print_instruction(tx, inner_ix, idls, &format!("#{}.{}", i, j), "        ");
+            });
+        });
+    });
+    idls.decode_events(tx).into_iter().for_each(|event| match event {
+        Ok(event) => println!("    event  {}::{}  {}", event.program, event.name, named_values(&event.fields)),
+        Err(e) => println!("    event  {}", e),
+    });
+}
+
+// handler for the 'dump_txs' CLI task:  'sol-data dump_txs <slot>'
+pub(crate) fn dump_txs_cli(args: &CliArguments) {
+    let slot = match args.task_args.as_slice() {
+        [slot_arg] => match slot_arg.parse::<u64>() {
+            Ok(slot) => slot,
+            Err(e) => return log_err(&format!("slot '{}':  {}", slot_arg, e)),
+        },
+        _ => return log_err("dump_txs needs 1 slot, e.g. 'sol-data dump_txs 100000000'"),
+    };
+    let dirs = match args.source.as_deref() {
+        Some(dir) => vec![dir],
+        None => vec![BLOCKS_DIR, CHUNKED_BLOCKS_DIR],
+    };
+    let idls = match load_idl_args(args) {
+        Ok(idls) => idls,
+        Err(e) => return log_err(&e),
+    };
+
+    for dir in dirs.into_iter().filter(|dir| Path::new(dir).is_dir()) {
+        match find_block(dir, slot) {
+            Ok(Some(block)) => {
+                println!("slot {},  parent {},  block time:  {},  transactions:  {}", block.slot, block.parent_slot,
+                         block.block_time.map(|t| t.to_string()).unwrap_or_default(), block.transactions.len());
+                if !idls.is_empty() { println!("programs with an IDL:  {}", idls.len()) }
+                block.transactions.iter().enumerate().for_each(|(i, tx)| print_tx(tx, i, &idls));
+                return;
+            },
+            Ok(None) => {},
+            Err(e) => return log_err(&e),
+        }
+    }
+    log_err(&format!("no block found for slot {}", slot));
+}
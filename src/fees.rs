@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+use solana_program::{pubkey::Pubkey, native_token::lamports_to_sol};
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, percentiles, ranked},
+    normalized::{NormalizedBlock, NormalizedTx}
+};
+
+
+// what transactions paid in fees:  overall, per block, per signature, per fee payer & per program
+#[derive(Default)]
+pub(crate) struct FeeAnalysis {
+    top: usize,
+    total: Fees,
+    failed: Fees,                               // paid by transactions that failed anyway
+    per_block: BTreeMap<u64, Fees>,             // by slot
+    // fee per signature -> transactions paying it. there are only ever a few distinct values,
+    // so percentiles are exact without keeping every transaction's fee
+    per_signature: BTreeMap<u64, u64>,
+    payers: HashMap<Pubkey, Fees>,
+    // each transaction's whole fee counts towards every program its top-level instructions invoke,
+    // so these add up to more than the total
+    programs: HashMap<Pubkey, Fees>,
+    report: Option<FeeReport>,                  // filled in by finalize()
+}
+
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+struct Fees {
+    txs: u64,
+    lamports: u64,
+}
+
+impl Fees {
+    fn add(&mut self, lamports: u64) {
+        self.txs += 1;
+        self.lamports += lamports;
+    }
+
+    fn merge(&mut self, other: Fees) {
+        self.txs += other.txs;
+        self.lamports += other.lamports;
+    }
+}
+
+#[derive(Serialize)]
+struct FeeReport {
+    total: Fees,
+    failed: Fees,
+    per_signature_percentiles: Vec<FeePercentile>,
+    top_payers: Vec<KeyFees>,
+    programs: Vec<KeyFees>,
+    per_block: Vec<BlockFees>,
+}
+
+#[derive(Serialize)]
+struct FeePercentile {
+    percentile: u8,
+    lamports: u64,
+}
+
+#[derive(Serialize)]
+struct KeyFees {
+    pubkey: String,
+    #[serde(flatten)]
+    fees: Fees,
+}
+
+#[derive(Serialize)]
+struct BlockFees {
+    slot: u64,
+    #[serde(flatten)]
+    fees: Fees,
+}
+
+impl FeeAnalysis {
+    pub fn new(top: usize) -> FeeAnalysis {
+        FeeAnalysis { top, ..Default::default() }
+    }
+}
+
+// most lamports first
+fn top_fees(fees: &HashMap<Pubkey, Fees>, limit: usize) -> Vec<KeyFees> {
+    let mut sorted: Vec<(&Pubkey, &Fees)> = fees.iter().collect();
+    ranked(&mut sorted, |(_, f)| f.lamports, |a, b| a.0.cmp(b.0));
+    sorted.into_iter().take(limit).map(|(key, fees)| KeyFees { pubkey: key.to_string(), fees: *fees }).collect()
+}
+
+impl Analyzer for FeeAnalysis {
+    fn empty(&self) -> FeeAnalysis {
+        FeeAnalysis::new(self.top)
+    }
+
+    fn tx(&mut self, block: &NormalizedBlock, tx: &NormalizedTx) {
+        let fee = match &tx.meta {
+            Some(meta) => meta.fee,
+            None => return,
+        };
+
+        self.total.add(fee);
+        if tx.failed() { self.failed.add(fee) }
+        self.per_block.entry(block.slot).or_default().add(fee);
+        let signatures = tx.signatures.len().max(1) as u64;
+        *self.per_signature.entry(fee / signatures).or_insert(0) += 1;
+        if let Some(payer) = tx.fee_payer() { self.payers.entry(payer).or_default().add(fee) }
+
+        let programs: HashSet<Pubkey> = tx.instructions.iter().map(|ix| ix.program_id).collect();
+        programs.into_iter().for_each(|program| self.programs.entry(program).or_default().add(fee));
+    }
+
+    fn merge(&mut self, other: FeeAnalysis) {
+        self.total.merge(other.total);
+        self.failed.merge(other.failed);
+        other.per_block.into_iter().for_each(|(slot, fees)| self.per_block.entry(slot).or_default().merge(fees));
+        other.per_signature.into_iter().for_each(|(fee, count)| *self.per_signature.entry(fee).or_insert(0) += count);
+        other.payers.into_iter().for_each(|(payer, fees)| self.payers.entry(payer).or_default().merge(fees));
+        other.programs.into_iter().for_each(|(program, fees)| self.programs.entry(program).or_default().merge(fees));
+    }
+
+    fn finalize(&mut self) {
+        self.report = Some(FeeReport {
+            total: self.total,
+            failed: self.failed,
+            per_signature_percentiles: percentiles(&self.per_signature).into_iter()
+                .map(|p| FeePercentile { percentile: p.percentile, lamports: p.value }).collect(),
+            top_payers: top_fees(&self.payers, self.top),
+            programs: top_fees(&self.programs, self.programs.len()),
+            per_block: self.per_block.iter().map(|(slot, fees)| BlockFees { slot: *slot, fees: *fees }).collect(),
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        let sol = lamports_to_sol;
+        let blocks = report.per_block.len().max(1) as f64;
+
+        println!("transactions:  {},  total fees:  {:.6} SOL,  mean per block:  {:.6} SOL",
+                 report.total.txs, sol(report.total.lamports), sol(report.total.lamports) / blocks);
+        println!("failed transactions:  {},  fees they paid:  {:.6} SOL", report.failed.txs, sol(report.failed.lamports));
+
+        println!("\n    fee per signature (lamports)");
+        report.per_signature_percentiles.iter().for_each(|p| println!("    p{:<3} {:>12}", p.percentile, p.lamports));
+
+        println!("\n    {:<44} {:>10} {:>16}", "top fee payers", "txs", "SOL");
+        report.top_payers.iter().for_each(|k| {
+            println!("    {:<44} {:>10} {:>16.6}", k.pubkey, k.fees.txs, sol(k.fees.lamports));
+        });
+
+        println!("\n    {:<44} {:>10} {:>16}", "fees by invoked program", "txs", "SOL");
+        report.programs.iter().take(self.top).for_each(|k| {
+            println!("    {:<44} {:>10} {:>16.6}", k.pubkey, k.fees.txs, sol(k.fees.lamports));
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        match &self.report {
+            Some(report) => out.write_json("", report),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,17 +1,21 @@
 use std::{fs::{self, ReadDir, File, read_dir}, path::{Path, PathBuf}, string::String, io};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use solana_program::clock::UnixTimestamp;
 use solana_transaction_status::EncodedConfirmedBlock;
+use serde::{Serialize, Deserialize};
 use serde_json;
 
-use crate::{util::{log_err_none, log_err, timer, PATH_SEP}, analyze::{process_block_stream, CountedTxs}};
+use crate::{
+    util::{log_err_none, log_err, timer, PATH_SEP}, analyze::{process_block_stream, CountedTxs},
+    compression::{Compression, read_file, write_file, compress, COMPRESSION_EXTENSIONS},
+    manifest::ChunkEntry, slot_range::SlotRange, normalized::VoteFilter,
+    binary_chunk::{serialize_bin_chunk, load_blocks_chunk_bin},
+    normalized::{serialize_normalized_chunk, load_normalized_chunk_as_raw}
+};
 
 pub fn test_block_loads_buf(chunked_blocks_dir: &PathBuf) {
-    let dir = match read_dir(chunked_blocks_dir) {
-        Ok(rd) => rd,
-        Err(e) => return log_err(&e),
-    };
-    let paths = dir_file_paths(dir);
-    process_block_stream(paths.as_slice());
+    let paths = chunk_file_paths(chunked_blocks_dir);
+    process_block_stream(paths.as_slice(), &SlotRange::default(), None, VoteFilter::Include);
 }
 
 pub fn dir_file_paths(rd: ReadDir) -> Vec<PathBuf> {
@@ -28,25 +32,39 @@ pub fn dir_file_paths(rd: ReadDir) -> Vec<PathBuf> {
 }
 
 pub(crate) const BLOCKS_DIR: &str = "blocks/json";
-pub fn write_json_encoded_block(slot: u64, block: &EncodedConfirmedBlock) {
-    let json_r = serde_json::to_string(&block);
-    match json_r {
-        Ok(data) => {
-            let name = slot_file_name(BLOCKS_DIR, slot, ".json");
+pub(crate) const JSON_EXTENSION: &str = ".json";
 
-            if Path::exists(Path::new(&name)) {
-                println!("FILE {} ALREADY PRESENT, not overriding", name);
-            } else {
-                print!("writing file:  {}\n", name);
-                let _ = fs::write(name, data);
-            }
-        },
-        Err(e) => eprintln!("{}", e),
+// ".json", followed by the compression's extension if there is one
+pub(crate) fn json_extension(compression: Compression) -> String {
+    format!("{}{}", JSON_EXTENSION, compression.extension())
+}
+
+pub(crate) fn write_json_encoded_block(slot: u64, block: &EncodedConfirmedBlock, compression: Compression) {
+    let path = slot_json_path(BLOCKS_DIR, slot, compression);
+    match write_slot_file(BLOCKS_DIR, slot, block, compression) {
+        Ok(true) => println!("file written:  {}", path.display()),
+        Ok(false) => println!("FILE {} ALREADY PRESENT, not overriding", path.display()),
+        Err(e) => log_err(&e),
     }
 }
 
+// write a block to its sharded slot file under the dir, unless one's already there.
+// returns whether the file was written
+pub(crate) fn write_slot_file(dir: &str, slot: u64, block: &EncodedConfirmedBlock, compression: Compression)
+    -> io::Result<bool>
+{
+    let path = slot_json_path(dir, slot, compression);
+    if path.exists() { return Ok(false) }
+
+    let data = serde_json::to_vec(&block)?;
+    fs::create_dir_all(slot_shard_dir(dir, slot))?;
+    write_file(&path, &data, compression)?;
+    Ok(true)
+}
+
+// loaders decompress according to the file extension, so they read ".json", ".json.zst" & ".json.gz"
 pub fn load_block_json<P: AsRef<Path>>(path: P) -> Option<EncodedConfirmedBlock> {
-    match fs::read(&path) {
+    match read_file(&path) {
         Ok(data) => {
             match serde_json::from_slice::<EncodedConfirmedBlock>(&data) {
                 Ok(block) => Some(block),
@@ -62,7 +80,7 @@ pub fn load_block_json<P: AsRef<Path>>(path: P) -> Option<EncodedConfirmedBlock>
 }
 
 pub fn load_blocks_chunk_json<P: AsRef<Path>>(path: P) -> Option<Vec<SlotData>> {
-    match fs::read(path) {
+    match read_file(path) {
         Ok(data) => {
             match serde_json::from_slice::<Vec<SlotData>>(&data) {
                 Ok(slots) => Some(slots),
@@ -75,15 +93,127 @@ pub fn load_blocks_chunk_json<P: AsRef<Path>>(path: P) -> Option<Vec<SlotData>>
 
 
 const SLOT_PREFIX: &str = "slot_";
-pub(crate) fn slot_json_path(slot: u64) -> PathBuf {
-    let p = format!("{}{}{}{}.json", BLOCKS_DIR, PATH_SEP, SLOT_PREFIX, slot);
-    Path::new(&p).to_path_buf()
+
+// slot files are sharded into nested dirs, so no single directory holds more than 1000 of them:
+//   <dir>/<slot / 100000>/<slot / 1000>/slot_<slot>.json
+// all numbers are zero-padded, so plain string sorting matches slot order
+const SHARD_OUTER_SLOTS: u64 = 100_000;
+const SHARD_INNER_SLOTS: u64 = 1_000;
+const SHARD_OUTER_DIGITS: usize = 7;
+const SHARD_INNER_DIGITS: usize = 9;
+const SLOT_DIGITS: usize = 12;
+
+pub(crate) fn slot_shard_dir(dir: &str, slot: u64) -> String {
+    format!("{}{}{:0ow$}{}{:0iw$}", 
+        dir, PATH_SEP, slot / SHARD_OUTER_SLOTS, PATH_SEP, slot / SHARD_INNER_SLOTS,
+        ow = SHARD_OUTER_DIGITS, iw = SHARD_INNER_DIGITS)
+}
+
+pub(crate) fn slot_json_path(dir: &str, slot: u64, compression: Compression) -> PathBuf {
+    Path::new(&slot_file_name(dir, slot, &json_extension(compression))).to_path_buf()
 }
 
 pub(crate) fn slot_file_name(dir: &str, slot: u64, extension: &str) -> String {
+    format!("{}{}{}{:0w$}{}", 
+        slot_shard_dir(dir, slot), PATH_SEP, SLOT_PREFIX, slot, extension, w = SLOT_DIGITS)
+}
+
+// where a slot's file lived before sharding:  <dir>/slot_<slot>.json
+pub(crate) fn flat_slot_file_name(dir: &str, slot: u64, extension: &str) -> String {
     format!("{}{}{}{}{}", dir, PATH_SEP, SLOT_PREFIX, slot, extension)
 }
 
+// is there already a file for this slot, in either the sharded or the flat layout, with any compression?
+pub(crate) fn slot_file_exists(slot: u64) -> bool {
+    !existing_slot_files(BLOCKS_DIR, slot).is_empty()
+}
+
+// every file a slot has in a blocks dir, in either layout & with any compression
+pub(crate) fn existing_slot_files(dir: &str, slot: u64) -> Vec<PathBuf> {
+    COMPRESSION_EXTENSIONS.iter().flat_map(|compress_ext| {
+        let ext = format!("{}{}", JSON_EXTENSION, compress_ext);
+        [slot_file_name(dir, slot, &ext), flat_slot_file_name(dir, slot, &ext)]
+    })
+    .map(PathBuf::from)
+    .filter(|path| path.exists())
+    .collect()
+}
+
+fn is_slot_file(path: &Path) -> bool {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.starts_with(SLOT_PREFIX),
+        None => false,
+    }
+}
+
+// every slot file under a blocks dir, whether it's sharded, flat, or a mix of both.
+// only one directory's entries are held at a time while walking the shards
+pub(crate) fn slot_file_paths<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+    let rd = match read_dir(&dir) {
+        Ok(rd) => rd,
+        Err(e) => { log_err(&e); return vec![] }
+    };
+
+    let mut slot_paths = Vec::<PathBuf>::new();
+    for path in dir_file_paths(rd) {
+        if path.is_dir() {
+            slot_paths.append(&mut slot_file_paths(&path));
+        } else if is_slot_file(&path) {
+            slot_paths.push(path);
+        }
+    }
+    slot_paths
+}
+
+// move every slot file from the old flat layout into its shard dir
+pub(crate) fn migrate_flat_slot_files(dir: &str) -> Result<usize, io::Error> {
+    let flat_paths: Vec<PathBuf> = dir_file_paths(read_dir(dir)?)
+        .into_iter()
+        .filter(|p| p.is_file() && is_slot_file(p))
+        .collect();
+
+    println!("flat slot files to migrate:  {}", flat_paths.len());
+
+    let moved: usize = flat_paths.par_iter().map(|src_path| {
+        let slot = match slot_num_from_path(src_path) {
+            Some(s) => s,
+            None => return 0,
+        };
+        let ext = match slot_file_extension(src_path) {
+            Some(ext) => ext,
+            None => return 0,
+        };
+
+        if let Err(e) = fs::create_dir_all(slot_shard_dir(dir, slot)) {
+            log_err(&e);
+            return 0;
+        }
+
+        let dest_path = slot_file_name(dir, slot, &ext);
+        if Path::new(&dest_path).exists() {
+            println!("file {} already present, leaving {:?} in place", dest_path, src_path);
+            return 0;
+        }
+        match fs::rename(src_path, &dest_path) {
+            Ok(_) => 1,
+            Err(e) => { log_err(&e); 0 }
+        }
+    }).sum();
+
+    Ok(moved)
+}
+
+pub(crate) fn timed_migrate_flat_slot_files(dir: &str) {
+    println!("\nmigrating flat slot files in {} to sharded layout", dir);
+    let elapsed = timer(|| {
+        match migrate_flat_slot_files(dir) {
+            Ok(moved) => println!("slot files moved:  {}", moved),
+            Err(e) => log_err(&e),
+        }
+    });
+    println!("migration done, time:  {:3} seconds\n", elapsed.as_secs_f32());
+}
+
 
 const TX_COUNT_PRE: &str = "key_tx_count_";
 // TODO - should i write a trait for "save to file" ?
@@ -104,59 +234,172 @@ pub(crate) fn write_pubkey_counts(dir: &str, counts: &CountedTxs) {
 
 pub(crate) type SlotData = (u64, EncodedConfirmedBlock);
 
-pub(crate) fn chunk_json_name(first: u64, last: u64) -> String {
-    format!("slots_{}-{}.json", first, last)
+pub(crate) const BIN_EXTENSION: &str = ".bin";
+pub(crate) const NORMALIZED_EXTENSION: &str = ".norm";
+
+// how a chunk's blocks are serialized, decided by the file extension before any compression one
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ChunkFormat {
+    #[default]
+    Json,
+    Binary,
+    Normalized,
+}
+
+impl ChunkFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChunkFormat::Json => JSON_EXTENSION,
+            ChunkFormat::Binary => BIN_EXTENSION,
+            ChunkFormat::Normalized => NORMALIZED_EXTENSION,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> ChunkFormat {
+        let name = path.as_ref().to_string_lossy();
+        let no_compress = name.trim_end_matches(Compression::from_path(path.as_ref()).extension());
+        if no_compress.ends_with(BIN_EXTENSION) { ChunkFormat::Binary } 
+        else if no_compress.ends_with(NORMALIZED_EXTENSION) { ChunkFormat::Normalized } 
+        else { ChunkFormat::Json }
+    }
+
+    pub fn from_name(name: &str) -> Result<ChunkFormat, String> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(ChunkFormat::Json),
+            "bin" | "binary" => Ok(ChunkFormat::Binary),
+            "norm" | "normalized" => Ok(ChunkFormat::Normalized),
+            other => Err(format!("chunk format '{}' not recognized, use one of: json, bin, norm", other))
+        }
+    }
+
+    // where chunk_blocks puts chunks of this format
+    pub fn default_dir(&self) -> &'static str {
+        match self {
+            ChunkFormat::Json => CHUNKED_BLOCKS_DIR,
+            ChunkFormat::Binary => BIN_CHUNKED_BLOCKS_DIR,
+            ChunkFormat::Normalized => NORMALIZED_CHUNKED_BLOCKS_DIR,
+        }
+    }
+}
+
+const CHUNK_PREFIX: &str = "slots_";
+
+// chunks grouped on a boundary other than size have a label after their slot range
+// saying which boundary window they're in, like "slots_<first>-<last>_epoch<n>.json"
+pub(crate) fn chunk_file_name(first: u64, last: u64, label: &str, format: ChunkFormat, compression: Compression) 
+    -> String 
+{
+    format!("{}{}-{}{}{}{}", CHUNK_PREFIX, first, last, label, format.extension(), compression.extension())
+}
+
+// the slot range & label parts of a chunk file's name
+fn chunk_name_parts(chunk_file_name: &str) -> Option<(&str, &str)> {
+    let stem = chunk_file_name.strip_prefix(CHUNK_PREFIX)?.split('.').next()?;
+    Some(match stem.find('_') {
+        Some(i) => stem.split_at(i),
+        None => (stem, ""),
+    })
+}
+
+// the slot range in a chunk file's name, "slots_<first>-<last>[_<label>].<ext>"
+pub(crate) fn parse_chunk_slots(chunk_file_name: &str) -> Option<(u64, u64)> {
+    let (range, _) = chunk_name_parts(chunk_file_name)?;
+    let (first, last) = range.split_once('-')?;
+    Some((first.parse::<u64>().ok()?, last.parse::<u64>().ok()?))
 }
 
-pub(crate) fn chunk_name(chunk: &Vec<SlotData>) -> String {
+// the label in a chunk file's name, with its leading '_', or "" if there's none
+pub(crate) fn parse_chunk_label(chunk_file_name: &str) -> &str {
+    chunk_name_parts(chunk_file_name).map_or("", |(_, label)| label)
+}
+
+pub(crate) const TIME_WINDOW_LABEL: &str = "_t";
+
+// the block time window in a time-windowed chunk's label, "_t<start>-<end>"
+pub(crate) fn parse_chunk_time_window(chunk_file_name: &str) -> Option<(UnixTimestamp, UnixTimestamp)> {
+    let window = parse_chunk_label(chunk_file_name).strip_prefix(TIME_WINDOW_LABEL)?;
+    let (start, end) = window.split_once('-')?;
+    Some((start.parse::<UnixTimestamp>().ok()?, end.parse::<UnixTimestamp>().ok()?))
+}
+
+pub(crate) fn chunk_name(chunk: &[SlotData], label: &str, format: ChunkFormat, compression: Compression) -> String {
     if chunk.is_empty() { return "EMPTY".to_string() }
     // first() / last() only return None if chunk is empty, unwrap() be safe
     let first= chunk.first().unwrap().0;
     let last = chunk.last().unwrap().0;
-    chunk_json_name(first, last)
+    chunk_file_name(first, last, label, format, compression)
 }
 
 pub(crate) const CHUNKED_BLOCKS_DIR: &str = "blocks/json_chunked";
+pub(crate) const BIN_CHUNKED_BLOCKS_DIR: &str = "blocks/bin_chunked";
+pub(crate) const NORMALIZED_CHUNKED_BLOCKS_DIR: &str = "blocks/norm_chunked";
+
+fn is_chunk_file(path: &Path) -> bool {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.starts_with(CHUNK_PREFIX),
+        None => false,
+    }
+}
 
-pub(crate) fn write_blocks_json_chunk(chunk: &Vec<SlotData>) {
-    let file_name = chunk_name(chunk);
+// every chunk file in a chunk dir, leaving out its manifest & anything else that isn't a chunk
+pub(crate) fn chunk_file_paths<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+    match read_dir(&dir) {
+        Ok(rd) => dir_file_paths(rd).into_iter().filter(|p| is_chunk_file(p)).collect(),
+        Err(e) => { log_err(&e); vec![] }
+    }
+}
 
-    match serde_json::to_string(chunk) {
+// returns the new chunk's manifest entry, or None if there was nothing to write or the file was already there.
+// Err if the chunk can't be serialized in full or written
+pub(crate) fn write_blocks_chunk(dir: &str, chunk: &[SlotData], label: &str, 
+    format: ChunkFormat, compression: Compression) -> Result<Option<ChunkEntry>, String>
+{
+    if chunk.is_empty() { return Ok(None) }
+    let file_name = chunk_name(chunk, label, format, compression);
+
+    let serialized = match format {
+        ChunkFormat::Json => serde_json::to_vec(chunk).map_err(|e| e.to_string()),
+        ChunkFormat::Binary => serialize_bin_chunk(chunk),
+        ChunkFormat::Normalized => serialize_normalized_chunk(chunk),
+    };
+    match serialized {
         Ok(data) => {
-            let path_str = format!("{}{}{}",CHUNKED_BLOCKS_DIR, PATH_SEP, &file_name);
+            let path_str = format!("{}{}{}", dir, PATH_SEP, &file_name);
             let path = Path::new(&path_str);
 
-            if Path::exists(&path) {
+            if Path::exists(path) {
                 println!("file {} already present, not overriding", &file_name);
-            } else {
-                //print!("writing block chunk file:  {}\n", p.to_str().unwrap());
-                let _ = fs::write(path, data);
+                return Ok(None);
+            }
+            // compress in memory first, so the checksum covers exactly what's on disk
+            let file_data = compress(&data, compression).map_err(|e| e.to_string())?;
+            match fs::write(path, &file_data) {
+                Ok(_) => Ok(Some(ChunkEntry::new(&file_name, chunk, &file_data, format, compression))),
+                Err(e) => Err(format!("{}:  {}", path_str, e)),
             }
         },
-        Err(e) => log_err(&e),
+        Err(e) => Err(format!("{}:  {}", file_name, e)),
     }
 }
 
-// TODO - make this a From or To trait ?
-fn pathbuf_to_fname(src_path: &PathBuf) -> Option<String> {
-    let src_str = src_path.to_string_lossy();
-    let src_split = src_str.split(PATH_SEP);
-    if let Some(fname) = src_split.last() { Some(fname.to_owned()) }
-    else { None }
+// load a chunk of blocks in any format & compression
+pub fn load_blocks_chunk<P: AsRef<Path>>(path: P) -> Option<Vec<SlotData>> {
+    match ChunkFormat::from_path(&path) {
+        ChunkFormat::Json => load_blocks_chunk_json(path),
+        ChunkFormat::Binary => load_blocks_chunk_bin(path),
+        ChunkFormat::Normalized => load_normalized_chunk_as_raw(path),
+    }
 }
 
 
 pub(crate) const BLOCK_SAMPLE_DIR: &str = "blocks/json_sample";
-// copy a sample of an existing folder's files
+// copy a sample of an existing blocks folder's slot files, keeping the sharded layout
 pub(crate) fn copy_sample<P: AsRef<Path>>(path: P, one_out_of: usize) -> Result<(), std::io::Error> {
     println!("\ncopying 1 out of every {} slot_.json files to {}", one_out_of, BLOCK_SAMPLE_DIR);
     
-    let read_dir = match fs::read_dir(path) {
-        Ok(rd) => rd,
-        Err(e) => { log_err(&e); return Err(e) }
-    };
+    if let Err(e) = fs::metadata(&path) { log_err(&e); return Err(e) }
 
-    let dir_paths = dir_file_paths(read_dir);
+    let dir_paths = slot_file_paths(path);
     let sample_size = dir_paths.len() / one_out_of;
     println!("sample size:  {}", sample_size);
 
@@ -171,10 +414,15 @@ pub(crate) fn copy_sample<P: AsRef<Path>>(path: P, one_out_of: usize) -> Result<
             Err(e) => { return log_err(&e); }       // don't stop if 1 fails, just log
         };
 
-        let file_name = if let Some(name) = pathbuf_to_fname(&src_path) { name } 
-                        else { return };
-                        
-        let dest_path = format!("{}{}{}", BLOCK_SAMPLE_DIR, PATH_SEP, file_name);
+        let (slot, ext) = match (slot_num_from_path(src_path), slot_file_extension(src_path)) {
+            (Some(slot), Some(ext)) => (slot, ext),
+            _ => return,
+        };
+        if let Err(e) = fs::create_dir_all(slot_shard_dir(BLOCK_SAMPLE_DIR, slot)) {
+            return log_err(&e);
+        }
+
+        let dest_path = slot_file_name(BLOCK_SAMPLE_DIR, slot, &ext);
 
         match File::create(dest_path) {
             Ok(mut dest) => {
@@ -212,13 +460,19 @@ fn parse_slot_num(slot_file_name: &str) -> Option<u64> {
     }
 }
 
-pub(crate) fn slot_num_from_path(slot_path: &PathBuf) -> Option<u64> {
-    match slot_path.as_os_str().to_str() {
-        Some(p) => parse_slot_num(p),
+pub(crate) fn slot_num_from_path(slot_path: &Path) -> Option<u64> {
+    match slot_path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => parse_slot_num(name),
         None => None,
     }
 }
 
+// everything after the first '.' in the file name, dot included - e.g. ".json"
+pub(crate) fn slot_file_extension(slot_path: &Path) -> Option<String> {
+    let name = slot_path.file_name()?.to_str()?;
+    name.find('.').map(|i| name[i..].to_string())
+}
+
 
 #[derive(Debug)]
 pub(crate) struct FileSizeStats {
@@ -227,11 +481,8 @@ pub(crate) struct FileSizeStats {
 }
 
 pub(crate) fn dir_size_stats<P: AsRef<Path>>(path: P) -> Result<FileSizeStats, std::io::Error> {
-    let dir = match fs::read_dir(path) {
-        Ok(rd) => rd,
-        Err(e) => return Err(e),
-    };
-    let file_paths = dir_file_paths(dir);
+    fs::metadata(&path)?;
+    let file_paths = slot_file_paths(path);
     let count = file_paths.len();
 
     let size_sum: usize = file_paths.par_iter().map(get_file_size).sum();
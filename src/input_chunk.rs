@@ -1,134 +1,443 @@
-use std::{fs::{read_dir, ReadDir}, path::{PathBuf}, cmp::max};
+use std::{fs, path::{PathBuf}, ops::Range};
 
 use rayon::{iter::{IntoParallelRefIterator, ParallelIterator}, current_num_threads};
+use solana_program::{clock::UnixTimestamp, epoch_schedule::EpochSchedule};
 
 use crate::{
-    cli::CliArguments,
-    util::{timer, MEGABYTE, do_or_die, log_err}, 
+    cli::CliArguments, compression::Compression,
+    manifest::{ChunkEntry, update_manifest, check_chunk_order, last_chunk},
+    util::{timer, MEGABYTE, do_or_die, log_err, log_err_none}, 
     files::{
-        BLOCKS_DIR, SlotData, dir_file_paths, get_file_size, 
-        slot_num_from_path, load_block_json, write_blocks_json_chunk
+        BLOCKS_DIR, SlotData, slot_file_paths, get_file_size, 
+        slot_num_from_path, load_block_json, load_blocks_chunk, write_blocks_chunk, ChunkFormat,
+        TIME_WINDOW_LABEL
     } 
 };
 
 
-type SizedPath<'a> = (&'a PathBuf, usize);      // file's path + size in bytes
+// a chunk dir's last chunk, when it's being merged with the new blocks after it
+struct Tail {
+    first: u64,
+    last: u64,
+    path: PathBuf,
+}
 
-// given a dir of many single-block .json files, group the inputs sequentially,  
-// each group sized as close to the limit as possible.
-// parse those groups, write them to single files in the out dir
-pub(crate) fn chunk_blocks_by_size(src_dir: ReadDir, max_input_bytes: usize) {
-    let src_paths = dir_file_paths(src_dir);
-    let src_sizes: Vec<SizedPath> = src_paths
-        .par_iter()
-        .map(|p| (p, get_file_size(p)))
-        .collect();
+pub(crate) struct ChunkSettings {
+    pub boundary: ChunkBoundary,
+    pub max_gap: Option<u64>,           // end a chunk wherever more slots than this are missing
+    pub merge_tail: bool,               // top up an undersized last chunk with the new blocks
+    pub format: ChunkFormat,
+    pub compression: Compression,
+}
+
+// given a dir of many single-block .json files, group the inputs sequentially into chunks
+// on the settings' boundary, & write each to a single file in the out dir.
+// inputs are sorted by slot first, so chunks cover disjoint, ascending slot ranges.
+// only slots after the out dir's last chunk are chunked, so re-running just adds newer blocks
+pub(crate) fn chunk_blocks(src_dir: &str, settings: &ChunkSettings) {
+    let out_dir = settings.format.default_dir();
+    let last = last_chunk(out_dir);
+    let mut slot_paths = sorted_slot_paths(src_dir);
+    println!("source file count:  {}", slot_paths.len());
+
+    if let Some((_, chunked_through, _)) = last {
+        slot_paths.retain(|(slot, _)| *slot > chunked_through);
+        println!("already chunked through slot {},  new slot files:  {}", chunked_through, slot_paths.len());
+    }
+    if slot_paths.is_empty() { return println!("no new slots to chunk") }
+
+    let tail = match last {
+        Some(chunk) if settings.merge_tail => mergeable_tail(chunk, slot_paths[0].0, settings),
+        _ => None,
+    };
+    if let Some(t) = &tail { println!("merging last chunk {:?} with the new slots", t.path) }
+
+    let entries = match settings.boundary {
+        ChunkBoundary::Size(max_bytes) => chunk_by_size(slot_paths, tail.as_ref(), max_bytes, settings),
+        _ => chunk_on_boundary(&slot_paths, tail.as_ref(), settings),
+    };
 
-    println!("source file count:  {}", src_sizes.len());
+    // the old last chunk is only removed once the chunk it was merged into is written
+    let mut removed = Vec::<String>::new();
+    if let Some(t) = &tail {
+        let tail_name = t.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if entries.iter().any(|e| e.first_slot == t.first && e.file != tail_name) {
+            match fs::remove_file(&t.path) {
+                Ok(_) => removed.push(tail_name),
+                Err(e) => log_err(&e),
+            }
+        }
+    }
+    update_manifest(out_dir, entries, &removed);
 
-    let nt = current_num_threads();
-    let task_count: usize = max((nt / 2) + (nt / 6) - 1, 1);
-    // actual task count is often +1, because of the remainder
-    let task_len = src_sizes.len() / task_count;
-    let sizes_chunks: Vec<&[SizedPath]> = src_sizes.chunks(task_len).collect();
+    if let Err(problems) = check_chunk_order(out_dir) {
+        log_err(&format!("{} has chunks that overlap or are out of order, re-chunk it into an empty directory:", out_dir));
+        problems.iter().for_each(log_err);
+    }
+}
 
-    // parallelizes, with a minor issue: the last chunk in each slice being undersized
-    // slice often runs out of src paths before full chunk accrues
-    // almost always enough output files that this is not an issue
-    let input_path_chunks: Vec<Vec<&PathBuf>> = sizes_chunks
+// group slot files by their size on disk, each group as close to the limit as possible.
+// planning is one sequential pass over the sizes, only loading & writing the chunks is parallel.
+// a merged tail goes in front of the new slots, as if it were one more input file, sized by its file on disk too
+fn chunk_by_size(mut slot_paths: Vec<(u64, PathBuf)>, tail: Option<&Tail>, max_bytes: usize, 
+    settings: &ChunkSettings) -> Vec<ChunkEntry> 
+{
+    if let Some(t) = tail { slot_paths.insert(0, (t.last, t.path.clone())) }
+
+    let sized_slots: Vec<SizedSlot> = slot_paths
         .par_iter()
-        .flat_map(|&paths| sized_path_chunks(&paths, max_input_bytes))
+        .map(|(slot, p)| (*slot, get_file_size(p)))
         .collect();
-    
-    println!("output chunk count:  {}", input_path_chunks.len());
 
-    input_path_chunks.par_iter().for_each(|chunk| {
+    let planned = plan_sized_chunks(&sized_slots, max_bytes, settings.max_gap);
+    println!("output chunk count:  {}", planned.len());
+
+    let out_dir = settings.format.default_dir();
+    planned.par_iter().filter_map(|range| {
         // given the chunk of input paths, load and parse them, discarding any that don't parse.
-        let slot_data: Vec<SlotData> = chunk.iter()
-        .filter_map(|&path| {
-            match load_block_json(path) {
-                Some(ecb) => {
-                    match slot_num_from_path(path) {
-                        Some(num) => Some((num, ecb)),
-                        None => None,
-                    }
-                },
-                None => None,
-            }
+        let slot_data: Vec<SlotData> = slot_paths[range.clone()].iter()
+        .flat_map(|(_, path)| {
+            if tail.is_some_and(|t| *path == t.path) { return load_blocks_chunk(path).unwrap_or_default() }
+            load_slot_file(path).into_iter().collect()
         })
         .collect(); 
 
         // after a chunk is collected, save it to a file 
-        write_blocks_json_chunk(&slot_data);
-    });
+        write_blocks_chunk(out_dir, &slot_data, "", settings.format, settings.compression).unwrap_or_else(log_err_none)
+    }).collect()
 }
 
-// get sequential groups of input paths that each total as close to the size limit as possible.
-fn sized_path_chunks<'a>(inputs: &'a [SizedPath], max_bytes: usize) -> Vec<Vec<&'a PathBuf>> {
-    let mut chunk_outputs = Vec::<Vec<&PathBuf>>::new();
-    let mut data = Vec::<&PathBuf>::new();
+type SizedSlot = (u64, usize);      // slot number + its file's size in bytes
 
-    let pb: PathBuf = Default::default();
-    let last_input = match inputs.last() {
-        Some(&last) => last,
-        None => {
-            log_err("inputs.last() was None, shouldn't be - in sized_path_chunks()");
-            (&pb, 0 as usize)
-        }
-    };
-    
+// split inputs, in slot order, into consecutive chunks whose sizes total as close to 'max_bytes' 
+// as possible without going over.  returns each chunk's range of input indices.
+// a single input bigger than the limit gets a chunk of its own, & whatever's left at the end
+// makes 1 last, undersized chunk.  if 'max_gap' is given, a chunk also ends 
+// wherever more than that many slots are missing between 2 inputs
+fn plan_sized_chunks(inputs: &[SizedSlot], max_bytes: usize, max_gap: Option<u64>) -> Vec<Range<usize>> {
+    let mut planned = Vec::<Range<usize>>::new();
+    let mut start: usize = 0;
     let mut size_count: usize = 0;
-    inputs.iter().for_each(|sized_path| {
-        let path = sized_path.0;
-        let size = sized_path.1;
-        let mut pushed = false;
-
-        if size > max_bytes && size_count == 0 {
-            // this 1 block is bigger than our target chunk size, make a chunk of 1
-            println!("single block chunk:  {} bytes", size);
-            data.push(path);
-            size_count += size;
-            pushed = true;
-        }
-        
-        let next_size = size_count + size;
-        if next_size < max_bytes && path == last_input.0 {
-            // println!("pushing undersize chunk from end of iterator");
-            data.push(path);
-            size_count += size;
-            pushed = true;
+
+    for (i, (slot, size)) in inputs.iter().enumerate() {
+        if i > start {
+            let after_gap = max_gap.is_some_and(|gap| slot - inputs[i - 1].0 - 1 > gap);
+            if after_gap || size_count + size > max_bytes {
+                planned.push(start..i);
+                start = i;
+                size_count = 0;
+            }
         }
+        if *size > max_bytes { println!("single block chunk:  {} bytes", size) }
+        size_count += size;
+    }
+    if start < inputs.len() { planned.push(start..inputs.len()) }
+
+    planned
+}
+
+// stream the slot files through a grouper, a batch at a time, since which chunk a block
+// goes in can depend on its block time.  a merged tail's blocks go first
+fn chunk_on_boundary(slot_paths: &[(u64, PathBuf)], tail: Option<&Tail>, settings: &ChunkSettings) 
+    -> Vec<ChunkEntry> 
+{
+    let tail_blocks = tail.and_then(|t| load_blocks_chunk(&t.path));
+    let batches = tail_blocks.into_iter().chain(
+        slot_paths.chunks(current_num_threads() * SLOT_FILE_BATCH_PER_THREAD).map(|batch| {
+            batch.par_iter().filter_map(|(_, path)| load_slot_file(path)).collect::<Vec<SlotData>>()
+        })
+    );
+
+    let grouper = BlockGrouper::new(settings.boundary, settings.max_gap);
+    let entries = write_grouped(batches, grouper, settings.format.default_dir(), settings.format, settings.compression);
+    println!("output chunk count:  {}", entries.len());
+    entries
+}
+
+const SLOT_FILE_BATCH_PER_THREAD: usize = 16;
+
+fn load_slot_file(path: &PathBuf) -> Option<SlotData> {
+    Some((slot_num_from_path(path)?, load_block_json(path)?))
+}
+
+// the dir's last chunk, if new blocks can be merged into it:  when chunking by size, only if it's undersized.
+// it can't be if a gap too big to chunk across comes before the first new slot
+fn mergeable_tail(last: (u64, u64, PathBuf), first_new_slot: u64, settings: &ChunkSettings) -> Option<Tail> {
+    let (first, last, path) = last;
+    if settings.max_gap.is_some_and(|gap| first_new_slot - last - 1 > gap) { return None }
+
+    match settings.boundary {
+        // measured on disk, the same as the slot files it'll be planned with
+        ChunkBoundary::Size(max_bytes) if get_file_size(&path) < max_bytes => Some(Tail { first, last, path }),
+        ChunkBoundary::Size(_) => None,
+        // the grouper decides whether the new blocks share the tail's window
+        _ => Some(Tail { first, last, path }),
+    }
+}
+
+// every slot file under the dir, sorted by slot number.
+// if a slot has more than 1 file (e.g. both compressed & not), only the first is kept,
+// so no slot can end up in 2 chunks
+fn sorted_slot_paths(src_dir: &str) -> Vec<(u64, PathBuf)> {
+    let mut slot_paths: Vec<(u64, PathBuf)> = slot_file_paths(src_dir)
+        .into_iter()
+        .filter_map(|p| slot_num_from_path(&p).map(|slot| (slot, p)))
+        .collect();
+    slot_paths.sort();
+    slot_paths.dedup_by_key(|(slot, _)| *slot);
+    slot_paths
+}
+
+// where one chunk ends & the next starts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ChunkBoundary {
+    Size(usize),                    // max bytes of json per chunk
+    Slots(u64),                     // chunks hold aligned ranges of this many slots
+    Epoch,                          // 1 chunk per epoch, on mainnet-beta's schedule
+    TimeWindow(UnixTimestamp),      // chunks hold aligned windows of this many seconds of block time
+}
 
-        let chunk_ready = next_size >= max_bytes;
-        if pushed || chunk_ready {
-            //println!("output chunk:  {} bytes,  {} blocks", size_count, data.len());
-            chunk_outputs.push(data.clone());
-            data.clear();
-            size_count = 0;
+impl ChunkBoundary {
+    // which window a block falls in, for boundaries that have them
+    fn window_key(&self, slot: u64, block_time: Option<UnixTimestamp>) -> Option<i64> {
+        match self {
+            ChunkBoundary::Size(_) => None,
+            ChunkBoundary::Slots(count) => Some((slot / count) as i64),
+            ChunkBoundary::Epoch => Some(EpochSchedule::without_warmup().get_epoch(slot) as i64),
+            ChunkBoundary::TimeWindow(seconds) => block_time.map(|t| t.div_euclid(*seconds)),
         }
-        if !pushed { 
-            data.push(path); 
-            size_count += size;
+    }
+
+    // goes in the chunk's file name, saying which window it holds.  e.g. "_t<start>-<end>" 
+    // for a time window, so tasks can tell what time a chunk covers from its name alone
+    pub fn label(&self, chunk: &[SlotData]) -> String {
+        let first_slot = match chunk.first() {
+            Some((slot, _)) => *slot,
+            None => return "".to_string(),
+        };
+        match self {
+            ChunkBoundary::Size(_) => "".to_string(),
+            ChunkBoundary::Slots(count) => {
+                let start = first_slot / count * count;
+                format!("_s{}-{}", start, start + count - 1)
+            },
+            ChunkBoundary::Epoch => format!("_epoch{}", EpochSchedule::without_warmup().get_epoch(first_slot)),
+            ChunkBoundary::TimeWindow(seconds) => match chunk.iter().find_map(|(_, b)| b.block_time) {
+                Some(time) => {
+                    let start = time.div_euclid(*seconds) * seconds;
+                    format!("{}{}-{}", TIME_WINDOW_LABEL, start, start + seconds - 1)
+                },
+                None => "".to_string(),
+            },
         }
-    });
+    }
+}
+
+// groups blocks, fed in slot order, into chunks on the given boundary
+pub(crate) struct BlockGrouper {
+    boundary: ChunkBoundary,
+    max_gap: Option<u64>,
+    current: Vec<SlotData>,
+    current_bytes: usize,
+    current_key: Option<i64>,
+}
+
+impl BlockGrouper {
+    pub fn new(boundary: ChunkBoundary, max_gap: Option<u64>) -> BlockGrouper {
+        BlockGrouper { boundary, max_gap, current: vec![], current_bytes: 0, current_key: None }
+    }
+
+    // add the next block, returning the chunk it completed if it starts a new one
+    pub fn push(&mut self, slot_data: SlotData) -> Option<Vec<SlotData>> {
+        let after_gap = match (self.max_gap, self.current.last()) {
+            (Some(gap), Some((last, _))) => slot_data.0 - last - 1 > gap,
+            _ => false,
+        };
+        let new_window = match self.boundary {
+            ChunkBoundary::Size(max_bytes) => {
+                let size = serde_json::to_vec(&slot_data.1).map_or(0, |json| json.len());
+                let full = self.current_bytes + size > max_bytes;
+                if full || after_gap { self.current_bytes = 0 }
+                self.current_bytes += size;
+                full
+            },
+            // blocks without a window (no block time) stay in the chunk before them
+            _ => match self.boundary.window_key(slot_data.0, slot_data.1.block_time) {
+                Some(key) if Some(key) != self.current_key => { self.current_key = Some(key); true },
+                _ => false,
+            },
+        };
+
+        let completed = if (new_window || after_gap) && !self.current.is_empty() { 
+            Some(std::mem::take(&mut self.current)) 
+        } else { None };
+        self.current.push(slot_data);
+        completed
+    }
+
+    // the last, partly filled chunk
+    pub fn finish(self) -> Option<Vec<SlotData>> {
+        if self.current.is_empty() { None } else { Some(self.current) }
+    }
+}
+
+// feed batches of blocks, in slot order, through the grouper & write each chunk as it's completed
+pub(crate) fn write_grouped<I: Iterator<Item = Vec<SlotData>>>(batches: I, mut grouper: BlockGrouper, 
+    dir: &str, format: ChunkFormat, compression: Compression) -> Vec<ChunkEntry> 
+{
+    let boundary = grouper.boundary;
+    let write_all = |chunks: &[Vec<SlotData>]| -> Vec<ChunkEntry> {
+        chunks.par_iter()
+            .filter_map(|chunk| {
+                write_blocks_chunk(dir, chunk, &boundary.label(chunk), format, compression).unwrap_or_else(log_err_none)
+            })
+            .collect()
+    };
 
-    chunk_outputs
+    let mut entries = Vec::<ChunkEntry>::new();
+    for batch in batches {
+        let completed: Vec<Vec<SlotData>> = batch.into_iter()
+            .filter_map(|slot_data| grouper.push(slot_data))
+            .collect();
+        entries.append(&mut write_all(&completed));
+    }
+    if let Some(last) = grouper.finish() { entries.append(&mut write_all(&[last])) }
+    entries
 }
 
 const NO_DIR_EXIT_MSG: &str = "can't proceed without a valid directory!\nexiting\n";
 
 // handler for the 'chunk_blocks' CLI task
-pub(crate) fn chunk_by_size_cli(args: &CliArguments) {
-    let size = if let Some(mbs) = args.chunk_size { MEGABYTE * mbs }
-               else { MEGABYTE * 2 };       // 2mb benchmarked best on dev machine, so it's default
-    
+pub(crate) fn chunk_blocks_cli(args: &CliArguments) {
     // TODO - make hardcoded BLOCKS_DIR path into CLI arg
     // exit if source can't be read
-    let src_dir = do_or_die(|| read_dir(BLOCKS_DIR), NO_DIR_EXIT_MSG);
+    do_or_die(|| fs::metadata(BLOCKS_DIR), NO_DIR_EXIT_MSG);
 
-    println!("chunking blocks by size:  {} kb per sequential group, max", size / 1024);
+    let boundary = chunk_boundary_arg(args);
+    match boundary {
+        ChunkBoundary::Size(size) => 
+            println!("chunking blocks by size:  {} kb per sequential group, max", size / 1024),
+        _ => println!("chunking blocks on boundary:  {:?}", boundary),
+    }
+    if let Some(gap) = args.max_gap { println!("splitting chunks at gaps of more than {} slots", gap) }
+    let format = args.chunk_format.unwrap_or_default();
+    println!("chunk file format:  {:?},  compression:  {:?}", format, args.compression);
+    if let Err(e) = fs::create_dir_all(format.default_dir()) { return log_err(&e) }
+
+    let settings = ChunkSettings {
+        boundary, max_gap: args.max_gap, merge_tail: args.merge_tail, format, compression: args.compression
+    };
     let elapsed = timer(|| {
-        chunk_blocks_by_size(src_dir, size);
+        chunk_blocks(BLOCKS_DIR, &settings);
     });
     println!("done, time:  {:3} seconds", elapsed.as_secs_f32());
-}
\ No newline at end of file
+}
+
+// the --slots, --epoch or --window arg, or else --mb.  clap rejects any 2 of them together, so only one is set
+pub(crate) fn chunk_boundary_arg(args: &CliArguments) -> ChunkBoundary {
+    match (args.slot_count, args.epoch_chunks, args.time_window) {
+        (Some(slots), _, _) => ChunkBoundary::Slots(slots),
+        (_, true, _) => ChunkBoundary::Epoch,
+        (_, _, Some(seconds)) => ChunkBoundary::TimeWindow(seconds),
+        // 2mb benchmarked best on dev machine, so it's default
+        _ => ChunkBoundary::Size(MEGABYTE * args.chunk_size.unwrap_or(2)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // consecutive slots, each with the given size
+    fn sized(sizes: &[usize]) -> Vec<SizedSlot> {
+        sizes.iter().enumerate().map(|(i, size)| (100 + i as u64, *size)).collect()
+    }
+
+    // every input is planned into exactly 1 chunk, in order
+    fn assert_covers_all(planned: &[Range<usize>], input_count: usize) {
+        let indices: Vec<usize> = planned.iter().flat_map(|r| r.clone()).collect();
+        assert_eq!(indices, (0..input_count).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn no_inputs_no_chunks() {
+        assert!(plan_sized_chunks(&[], 100, None).is_empty());
+    }
+
+    #[test]
+    fn chunks_fill_to_limit_without_going_over() {
+        let inputs = sized(&[40, 40, 20, 50, 50, 30, 30, 30]);
+        let planned = plan_sized_chunks(&inputs, 100, None);
+
+        assert_eq!(planned, vec![0..3, 3..5, 5..8]);
+        assert_covers_all(&planned, inputs.len());
+    }
+
+    #[test]
+    fn last_chunk_can_fill_to_exactly_the_limit() {
+        let inputs = sized(&[60, 30, 60, 30, 10]);
+        let planned = plan_sized_chunks(&inputs, 100, None);
+
+        assert_eq!(planned, vec![0..2, 2..5]);
+        assert_covers_all(&planned, inputs.len());
+    }
+
+    #[test]
+    fn final_remainder_is_its_own_chunk() {
+        let inputs = sized(&[60, 30, 60, 30, 60, 5]);
+        let planned = plan_sized_chunks(&inputs, 100, None);
+
+        // the last input doesn't fit the chunk before it, & still gets planned
+        assert_eq!(planned, vec![0..2, 2..4, 4..6]);
+        assert_covers_all(&planned, inputs.len());
+    }
+
+    #[test]
+    fn single_input_under_limit_is_one_chunk() {
+        assert_eq!(plan_sized_chunks(&sized(&[10]), 100, None), vec![0..1]);
+    }
+
+    #[test]
+    fn oversized_block_gets_its_own_chunk() {
+        let inputs = sized(&[30, 30, 250, 30, 30]);
+        let planned = plan_sized_chunks(&inputs, 100, None);
+
+        assert_eq!(planned, vec![0..2, 2..3, 3..5]);
+        assert_covers_all(&planned, inputs.len());
+    }
+
+    #[test]
+    fn oversized_blocks_first_last_and_in_a_row() {
+        let inputs = sized(&[150, 150, 20, 20, 150]);
+        let planned = plan_sized_chunks(&inputs, 100, None);
+
+        assert_eq!(planned, vec![0..1, 1..2, 2..4, 4..5]);
+        assert_covers_all(&planned, inputs.len());
+    }
+
+    #[test]
+    fn chunks_end_at_gaps_bigger_than_max() {
+        let inputs: Vec<SizedSlot> = vec![(10, 1), (11, 1), (13, 1), (20, 1), (21, 1)];
+
+        assert_eq!(plan_sized_chunks(&inputs, 100, Some(1)), vec![0..3, 3..5]);
+        assert_eq!(plan_sized_chunks(&inputs, 100, Some(0)), vec![0..2, 2..3, 3..5]);
+        assert_eq!(plan_sized_chunks(&inputs, 100, None), vec![0..5]);
+    }
+
+    #[test]
+    fn planning_is_deterministic_and_covers_everything() {
+        let sizes: Vec<usize> = (0..1000).map(|i| (i * 7919) % 173 + 1).collect();
+        let inputs = sized(&sizes);
+        let planned = plan_sized_chunks(&inputs, 500, None);
+
+        assert_eq!(planned, plan_sized_chunks(&inputs, 500, None));
+        assert_covers_all(&planned, inputs.len());
+        planned.iter().for_each(|range| {
+            let total: usize = inputs[range.clone()].iter().map(|(_, size)| size).sum();
+            assert!(total <= 500);
+            // a chunk only ends early if the next input wouldn't fit
+            if range.end < inputs.len() { assert!(total + inputs[range.end].1 > 500) }
+        });
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    normalized::{AccountRole, NormalizedBlock, NormalizedTx}
+};
+
+
+// how many transactions each public key appears in, the same total as the 'count_txs' task,
+// broken down by the role the key had in each of them
+#[derive(Default)]
+pub(crate) struct KeyCounts {
+    top: usize,
+    role: Option<AccountRole>,          // only keep keys seen in this role, sorted by it
+    counts: HashMap<Pubkey, RoleCounts>,
+    sorted: Vec<(Pubkey, RoleCounts)>,  // most seen first, filled in by finalize()
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+struct RoleCounts {
+    txs: u32,
+    fee_payer: u32,
+    signer: u32,
+    writable: u32,
+    readonly: u32,
+}
+
+impl RoleCounts {
+    fn add(&mut self, role: AccountRole) {
+        self.txs += 1;
+        match role {
+            AccountRole::FeePayer => self.fee_payer += 1,
+            AccountRole::Signer => self.signer += 1,
+            AccountRole::Writable => self.writable += 1,
+            AccountRole::Readonly => self.readonly += 1,
+        }
+    }
+
+    fn merge(&mut self, other: RoleCounts) {
+        self.txs += other.txs;
+        self.fee_payer += other.fee_payer;
+        self.signer += other.signer;
+        self.writable += other.writable;
+        self.readonly += other.readonly;
+    }
+
+    // the count being ranked by:  the role's, or every appearance without one
+    fn count(&self, role: Option<AccountRole>) -> u32 {
+        match role {
+            Some(AccountRole::FeePayer) => self.fee_payer,
+            Some(AccountRole::Signer) => self.signer,
+            Some(AccountRole::Writable) => self.writable,
+            Some(AccountRole::Readonly) => self.readonly,
+            None => self.txs,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct KeyCountRow {
+    pubkey: String,
+    #[serde(flatten)]
+    counts: RoleCounts,
+}
+
+impl KeyCounts {
+    pub fn new(top: usize, role: Option<AccountRole>) -> KeyCounts {
+        KeyCounts { top, role, ..Default::default() }
+    }
+}
+
+impl Analyzer for KeyCounts {
+    fn empty(&self) -> KeyCounts {
+        KeyCounts::new(self.top, self.role)
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, tx: &NormalizedTx) {
+        tx.accounts.iter().enumerate().for_each(|(i, acct)| {
+            self.counts.entry(acct.pubkey).or_default().add(tx.account_role(i));
+        });
+    }
+
+    fn merge(&mut self, other: KeyCounts) {
+        other.counts.into_iter().for_each(|(key, counts)| self.counts.entry(key).or_default().merge(counts));
+    }
+
+    fn finalize(&mut self) {
+        let role = self.role;
+        self.sorted = self.counts.iter()
+            .filter(|(_, c)| c.count(role) > 0)
+            .map(|(k, c)| (*k, *c))
+            .collect();
+        ranked(&mut self.sorted, |(_, c)| c.count(role), |a, b| a.0.cmp(&b.0));
+    }
+
+    fn print_report(&self) {
+        match self.role {
+            Some(role) => println!("public keys seen as {}:  {}", role.name(), self.sorted.len()),
+            None => println!("unique public keys counted:  {}", self.sorted.len()),
+        }
+        println!("    {:<44} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                 "pubkey", "txs", "fee payer", "signer", "writable", "readonly");
+        self.sorted.iter().take(self.top).for_each(|(key, c)| {
+            println!("    {:<44} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                     key.to_string(), c.txs, c.fee_payer, c.signer, c.writable, c.readonly);
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        let rows: Vec<KeyCountRow> = self.sorted.iter()
+            .map(|(key, counts)| KeyCountRow { pubkey: key.to_string(), counts: *counts })
+            .collect();
+        let suffix = self.role.map(|r| format!("_{}", r.name())).unwrap_or_default();
+        out.write_json(&suffix, &rows)
+    }
+}
@@ -2,8 +2,9 @@ use crate::{
     cli::*, tasks::*,
     client::SolClient, util::log_err,
     scrape::scrape_with_args, 
-    input_chunk::chunk_by_size_cli,
-    files::{BLOCKS_DIR,  CHUNKED_BLOCKS_DIR, timed_copy_sample},
+    input_chunk::chunk_blocks_cli, binary_chunk::convert_chunks_cli, manifest::verify_chunks_cli,
+    rechunk::{rechunk_cli, unchunk_cli}, compact::compact_blocks_cli, analyzer::analyze_cli, dump::dump_txs_cli,
+    files::{BLOCKS_DIR,  CHUNKED_BLOCKS_DIR, timed_copy_sample, timed_migrate_flat_slot_files},
     test_tasks::{load_perf_by_size, test_size_average, test_block_loads, test_get_block_production},
 };
 
@@ -14,6 +15,28 @@ pub mod scrape;
 pub mod cli;
 pub mod networks;
 pub mod tasks;
+mod compression;
+mod binary_chunk;
+mod normalized;
+mod chunk_stream;
+mod map_reduce;
+mod analyzer;
+mod key_counts;
+mod program_activity;
+mod fees;
+mod tx_errors;
+mod sol_flow;
+mod token_flow;
+mod decode;
+mod dump;
+mod anchor_idl;
+mod anchor_activity;
+mod votes;
+mod cpi_graph;
+mod manifest;
+mod slot_range;
+mod rechunk;
+mod compact;
 mod util;
 mod test_tasks;
 mod input_chunk;
@@ -26,11 +49,27 @@ fn main() {
         SCRAPE_BLOCKS_TASK =>
             scrape_with_args(&cli_args),
         CHUNK_BLOCKS_TASK =>
-            chunk_by_size_cli(&cli_args),
+            chunk_blocks_cli(&cli_args),
         BLOCK_SAMPLE_TASK => 
             timed_copy_sample(BLOCKS_DIR, cli_args.sample_rate),
+        MIGRATE_BLOCKS_TASK =>
+            timed_migrate_flat_slot_files(BLOCKS_DIR),
         COUNT_KEY_TXS_TASK => 
-            test_block_loads(CHUNKED_BLOCKS_DIR),
+            test_block_loads(cli_args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR), &cli_args.slot_range, cli_args.threads, cli_args.votes),
+        CONVERT_CHUNKS_TASK =>
+            convert_chunks_cli(&cli_args),
+        VERIFY_CHUNKS_TASK =>
+            verify_chunks_cli(&cli_args),
+        RECHUNK_TASK =>
+            rechunk_cli(&cli_args),
+        UNCHUNK_TASK =>
+            unchunk_cli(&cli_args),
+        COMPACT_BLOCKS_TASK =>
+            compact_blocks_cli(&cli_args),
+        ANALYZE_TASK =>
+            analyze_cli(&cli_args),
+        DUMP_TXS_TASK =>
+            dump_txs_cli(&cli_args),
         MEAN_FILE_SIZE_TASK => 
             test_size_average(BLOCKS_DIR),
         COMPARE_BLOCK_LOADS_TASK =>
@@ -0,0 +1,296 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Serialize, Deserialize};
+use solana_program::{hash::hash, clock::UnixTimestamp};
+
+use crate::{
+    cli::CliArguments, compression::Compression,
+    files::{SlotData, ChunkFormat, CHUNKED_BLOCKS_DIR, chunk_file_paths, parse_chunk_slots, load_blocks_chunk},
+    util::{log_err, PATH_SEP}
+};
+
+
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+// what one chunk file holds, recorded when it's written so nothing has to be parsed to find out
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ChunkEntry {
+    pub file: String,
+    pub first_slot: u64,
+    pub last_slot: u64,
+    pub block_count: usize,
+    pub tx_count: usize,
+    // earliest & latest block times in the chunk, for selecting chunks by time
+    #[serde(default)]
+    pub min_block_time: Option<UnixTimestamp>,
+    #[serde(default)]
+    pub max_block_time: Option<UnixTimestamp>,
+    pub bytes: u64,
+    pub checksum: String,           // sha256 of the file's bytes on disk, base58
+    pub format: ChunkFormat,
+    pub compression: String,
+    pub created: u64,               // unix timestamp, seconds
+}
+
+impl ChunkEntry {
+    // 'data' is exactly what was written to the file, after compression
+    pub fn new(file: &str, chunk: &[SlotData], data: &[u8], format: ChunkFormat, compression: Compression)
+        -> ChunkEntry
+    {
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        ChunkEntry {
+            file: file.to_string(),
+            first_slot: chunk.first().map_or(0, |s| s.0),
+            last_slot: chunk.last().map_or(0, |s| s.0),
+            block_count: chunk.len(),
+            tx_count: chunk.iter().map(|(_, block)| block.transactions.len()).sum(),
+            min_block_time: chunk.iter().filter_map(|(_, block)| block.block_time).min(),
+            max_block_time: chunk.iter().filter_map(|(_, block)| block.block_time).max(),
+            bytes: data.len() as u64,
+            checksum: hash(data).to_string(),
+            format,
+            compression: compression.name().to_string(),
+            created,
+        }
+    }
+}
+
+// every chunk in a chunk dir, kept in <dir>/manifest.json & sorted by slot
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct ChunkManifest {
+    pub chunks: Vec<ChunkEntry>
+}
+
+impl ChunkManifest {
+    // a dir without a manifest yet just has an empty one
+    pub fn load(dir: &str) -> Result<ChunkManifest, String> {
+        let path = manifest_path(dir);
+        if !Path::new(&path).exists() { return Ok(ChunkManifest::default()) }
+
+        let data = fs::read(&path).map_err(|e| e.to_string())?;
+        serde_json::from_slice::<ChunkManifest>(&data).map_err(|e| format!("{}:  {}", path, e))
+    }
+
+    pub fn save(&self, dir: &str) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(manifest_path(dir), json).map_err(|e| e.to_string())
+    }
+
+    // add entries, replacing any already recorded for the same file
+    pub fn upsert(&mut self, entries: Vec<ChunkEntry>) {
+        for entry in entries {
+            self.chunks.retain(|c| c.file != entry.file);
+            self.chunks.push(entry);
+        }
+        self.chunks.sort_by_key(|c| (c.first_slot, c.last_slot));
+    }
+
+    pub fn remove(&mut self, files: &[String]) {
+        self.chunks.retain(|c| !files.contains(&c.file));
+    }
+}
+
+fn manifest_path(dir: &str) -> String {
+    format!("{}{}{}", dir, PATH_SEP, MANIFEST_FILE)
+}
+
+// record newly written chunks in their dir's manifest, & forget any chunk files that were removed
+pub(crate) fn update_manifest(dir: &str, entries: Vec<ChunkEntry>, removed: &[String]) {
+    if entries.is_empty() && removed.is_empty() { return }
+
+    let mut manifest = match ChunkManifest::load(dir) {
+        Ok(m) => m,
+        Err(e) => {
+            log_err(&e);
+            log_err("manifest couldn't be read, starting a new one");
+            ChunkManifest::default()
+        }
+    };
+    let added = entries.len();
+    manifest.upsert(entries);
+    manifest.remove(removed);
+    match manifest.save(dir) {
+        Ok(_) => println!("manifest updated:  {} chunks added,  {} removed,  {} total", 
+                          added, removed.len(), manifest.chunks.len()),
+        Err(e) => log_err(&e),
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ChunkProblem {
+    Missing,                    // in the manifest, but the file is gone
+    Unlisted,                   // a chunk file the manifest doesn't know about
+    Corrupt(String),            // checksum, size or contents don't match the manifest
+    Overlap(String),            // slot range overlaps with the named chunk's
+}
+
+// check every chunk in a dir against its manifest
+pub(crate) fn verify_chunk_dir(dir: &str) -> Result<Vec<(String, ChunkProblem)>, String> {
+    let manifest = ChunkManifest::load(dir)?;
+
+    let mut problems: Vec<(String, ChunkProblem)> = manifest.chunks
+        .par_iter()
+        .filter_map(|entry| verify_chunk(dir, entry).map(|p| (entry.file.clone(), p)))
+        .collect();
+
+    chunk_file_paths(dir).iter().for_each(|path| {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if !manifest.chunks.iter().any(|c| c.file == name) {
+            problems.push((name, ChunkProblem::Unlisted));
+        }
+    });
+
+    let mut ranges: Vec<(u64, u64, &str)> = manifest.chunks.iter()
+        .map(|c| (c.first_slot, c.last_slot, c.file.as_str()))
+        .collect();
+    ranges.sort();
+    overlaps(&ranges).into_iter()
+        .for_each(|(file, other)| problems.push((file.to_string(), ChunkProblem::Overlap(other.to_string()))));
+
+    Ok(problems)
+}
+
+// each chunk that overlaps an earlier one, with the one it overlaps, from (first slot, last slot, name) sorted by slot.
+// a chunk overlaps if it starts at or before the furthest any earlier chunk reaches,
+// which needn't be its neighbor's:  [100-500], [200-250], [300-400] has the 3rd overlapping the 1st
+fn overlaps<'a>(sorted: &[(u64, u64, &'a str)]) -> Vec<(&'a str, &'a str)> {
+    let mut found = vec![];
+    let mut furthest: Option<&(u64, u64, &str)> = None;
+    for range in sorted {
+        match furthest {
+            Some(prev) if range.0 <= prev.1 => {
+                found.push((range.2, prev.2));
+                if range.1 > prev.1 { furthest = Some(range) }
+            },
+            _ => furthest = Some(range),
+        }
+    }
+    found
+}
+
+fn verify_chunk(dir: &str, entry: &ChunkEntry) -> Option<ChunkProblem> {
+    load_verified_chunk(dir, entry).err()
+}
+
+// re-read a chunk, only returning its blocks if it's exactly what the manifest recorded
+pub(crate) fn load_verified_chunk(dir: &str, entry: &ChunkEntry) -> Result<Vec<SlotData>, ChunkProblem> {
+    let path = format!("{}{}{}", dir, PATH_SEP, entry.file);
+    let data = match fs::read(&path) {
+        Ok(d) => d,
+        Err(_) => return Err(ChunkProblem::Missing),
+    };
+
+    if data.len() as u64 != entry.bytes {
+        return Err(ChunkProblem::Corrupt(format!("size {} bytes, expected {}", data.len(), entry.bytes)))
+    }
+    let checksum = hash(&data).to_string();
+    if checksum != entry.checksum {
+        return Err(ChunkProblem::Corrupt(format!("checksum {}, expected {}", checksum, entry.checksum)))
+    }
+
+    // the bytes match, make sure they still parse into what the manifest says they hold
+    match load_blocks_chunk(&path) {
+        Some(chunk) => {
+            let first = chunk.first().map_or(0, |s| s.0);
+            let last = chunk.last().map_or(0, |s| s.0);
+            if chunk.len() != entry.block_count || first != entry.first_slot || last != entry.last_slot {
+                Err(ChunkProblem::Corrupt(format!("holds {} blocks, slots {}-{}", chunk.len(), first, last)))
+            } else if chunk.windows(2).any(|pair| pair[1].0 <= pair[0].0) {
+                Err(ChunkProblem::Corrupt("blocks aren't in ascending slot order".to_string()))
+            } else { Ok(chunk) }
+        },
+        None => Err(ChunkProblem::Corrupt("couldn't be parsed".to_string())),
+    }
+}
+
+// the slot range of every chunk file in a dir, sorted.
+// ranges come from the manifest where it has them, otherwise from the chunk file names
+pub(crate) fn chunk_slot_ranges(dir: &str) -> Vec<(u64, u64, PathBuf)> {
+    let manifest = ChunkManifest::load(dir).unwrap_or_default();
+    let mut ranges: Vec<(u64, u64, PathBuf)> = chunk_file_paths(dir).into_iter().filter_map(|path| {
+        let name = path.file_name()?.to_string_lossy().to_string();
+        let (first, last) = match manifest.chunks.iter().find(|c| c.file == name) {
+            Some(entry) => (entry.first_slot, entry.last_slot),
+            None => parse_chunk_slots(&name)?,
+        };
+        Some((first, last, path))
+    }).collect();
+    ranges.sort();
+    ranges
+}
+
+// every chunk file in a dir in first slot order, then any whose slot range isn't known, by name
+pub(crate) fn chunk_paths_in_order(dir: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = chunk_slot_ranges(dir).into_iter().map(|(_, _, path)| path).collect();
+    let ranged: HashSet<PathBuf> = paths.iter().cloned().collect();
+    let mut unranged: Vec<PathBuf> = chunk_file_paths(dir).into_iter().filter(|p| !ranged.contains(p)).collect();
+    unranged.sort();
+    paths.extend(unranged);
+    paths
+}
+
+// the chunk holding the highest slots in a dir, if there are any chunks
+pub(crate) fn last_chunk(dir: &str) -> Option<(u64, u64, PathBuf)> {
+    chunk_slot_ranges(dir).into_iter().max_by_key(|(_, last, _)| *last)
+}
+
+// chunks in a dir must cover disjoint, ascending slot ranges.
+// cheap to check, & works for dirs without a manifest
+pub(crate) fn check_chunk_order(dir: &str) -> Result<(), Vec<String>> {
+    let ranges: Vec<(u64, u64, String)> = chunk_slot_ranges(dir).into_iter()
+        .map(|(first, last, path)| (first, last, path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()))
+        .collect();
+
+    let mut problems: Vec<String> = ranges.iter()
+        .filter(|(first, last, _)| first > last)
+        .map(|(_, _, name)| format!("{}:  first slot is after last slot", name))
+        .collect();
+    let named: Vec<(u64, u64, &str)> = ranges.iter().map(|(first, last, name)| (*first, *last, name.as_str())).collect();
+    overlaps(&named).into_iter().for_each(|(name, other)| problems.push(format!("{}:  overlaps {}", name, other)));
+
+    if problems.is_empty() { Ok(()) } else { Err(problems) }
+}
+
+// slot ranges can't be trusted to select chunks, or to merge results in order, if they overlap.
+// logs why & returns false if a dir's chunks can't be analyzed
+pub(crate) fn require_chunk_order(dir: &str) -> bool {
+    match check_chunk_order(dir) {
+        Ok(()) => true,
+        Err(problems) => {
+            log_err(&format!("can't analyze {}, its chunks overlap or are out of order:", dir));
+            problems.iter().for_each(log_err);
+            log_err("re-chunk the blocks into an empty directory, see the 'verify_chunks' task");
+            false
+        }
+    }
+}
+
+// handler for the 'verify_chunks' CLI task
+pub(crate) fn verify_chunks_cli(args: &CliArguments) {
+    let dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    println!("\nverifying chunks in {} against {}", dir, MANIFEST_FILE);
+
+    match verify_chunk_dir(dir) {
+        Ok(problems) if problems.is_empty() => println!("all chunks verified, no problems found\n"),
+        Ok(problems) => {
+            problems.iter().for_each(|(file, problem)| println!("    {}:  {:?}", file, problem));
+            println!("{} problems found\n", problems.len());
+        },
+        Err(e) => log_err(&e),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlaps_check_the_furthest_earlier_chunk() {
+        let ranges = [(100, 500, "a"), (200, 250, "b"), (300, 400, "c"), (501, 600, "d"), (600, 700, "e")];
+        assert_eq!(overlaps(&ranges), vec![("b", "a"), ("c", "a"), ("e", "d")]);
+        assert!(overlaps(&[(1, 1, "a"), (2, 5, "b")]).is_empty());
+    }
+}
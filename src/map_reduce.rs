@@ -0,0 +1,138 @@
+use std::{path::PathBuf, sync::atomic::{AtomicUsize, Ordering}, time::Instant};
+
+use rayon::{ThreadPoolBuilder, iter::{IntoParallelRefIterator, ParallelIterator}};
+
+use crate::util::log_err;
+
+
+// the blocks of 1 chunk, as they're read from its file
+pub(crate) type BlockIter<'a, T> = &'a mut dyn Iterator<Item = T>;
+
+// a chunk that couldn't be opened, or whose stream failed partway.
+// its partial output is left out of the result
+#[derive(Clone, Debug)]
+pub(crate) struct ChunkError {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+pub(crate) struct MapReduceOutput<C> {
+    pub result: C,
+    pub chunks_done: usize,
+    pub errors: Vec<ChunkError>,
+}
+
+impl<C> MapReduceOutput<C> {
+    pub fn print_summary(&self) {
+        println!("chunks analyzed:  {},  failed:  {}", self.chunks_done, self.errors.len());
+        if self.errors.is_empty() { return }
+        log_err("failed chunks were left out:");
+        self.errors.iter().for_each(|e| log_err(&format!("    {:?}:  {}", e.path, e.error)));
+    }
+}
+
+/*
+    runs an analysis over a set of chunk files:
+    * open each file as a stream of 'T', parsed one at a time
+    * 'map' the data streamed from each chunk to an output, 'C'
+    * 'reduce' all the chunks' outputs to one single 'C'
+    load, map & reduce can be closures, so they can capture whatever an analysis is parameterized by
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ChunkMapReduce {
+    threads: Option<usize>,         // cap on chunks processed at once, default: rayon's thread count
+    progress: bool,                 // print how many chunks are done as it goes
+}
+
+// how many progress lines to print over a whole run, at most
+const PROGRESS_STEPS: usize = 20;
+
+impl ChunkMapReduce {
+    pub fn new() -> ChunkMapReduce {
+        ChunkMapReduce::default()
+    }
+
+    pub fn threads(mut self, threads: Option<usize>) -> ChunkMapReduce {
+        self.threads = threads;
+        self
+    }
+
+    pub fn progress(mut self, progress: bool) -> ChunkMapReduce {
+        self.progress = progress;
+        self
+    }
+
+    pub fn run<T, C, I, L, M, R>(&self, paths: &[PathBuf], load: L, map: M, reduce: R) -> MapReduceOutput<C>
+    where
+        C: Send,
+        I: Iterator<Item = Result<T, String>>,
+        L: Fn(&PathBuf) -> Result<I, String> + Sync,
+        M: Fn(BlockIter<T>) -> C + Sync,
+        R: FnOnce(Vec<C>) -> C,
+    {
+        let pool = self.threads.and_then(|n| {
+            ThreadPoolBuilder::new().num_threads(n).build()
+                .map_err(|e| log_err(&format!("couldn't cap threads at {}, using the default:  {}", n, e)))
+                .ok()
+        });
+        let map_all = || self.map_chunks(paths, &load, &map);
+        let mapped = match &pool {
+            Some(p) => p.install(map_all),
+            None => map_all(),
+        };
+
+        let mut results = Vec::<C>::with_capacity(mapped.len());
+        let mut errors = Vec::<ChunkError>::new();
+        mapped.into_iter().for_each(|r| match r {
+            Ok(c) => results.push(c),
+            Err(e) => errors.push(e),
+        });
+
+        let chunks_done = results.len();
+        MapReduceOutput { result: reduce(results), chunks_done, errors }
+    }
+
+    // in parallel, stream each chunk's data through 'map'
+    fn map_chunks<T, C, I, L, M>(&self, paths: &[PathBuf], load: &L, map: &M) -> Vec<Result<C, ChunkError>>
+    where
+        C: Send,
+        I: Iterator<Item = Result<T, String>>,
+        L: Fn(&PathBuf) -> Result<I, String> + Sync,
+        M: Fn(BlockIter<T>) -> C + Sync,
+    {
+        let total = paths.len();
+        let step = total.div_ceil(PROGRESS_STEPS).max(1);
+        let done = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        paths.par_iter().map(|path| {
+            let result = map_chunk(path, load, map);
+
+            let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if self.progress && (finished.is_multiple_of(step) || finished == total) {
+                println!("chunks processed:  {} / {},  {:.1} seconds", finished, total, start.elapsed().as_secs_f32());
+            }
+            result
+        }).collect()
+    }
+}
+
+fn map_chunk<T, C, I, L, M>(path: &PathBuf, load: &L, map: &M) -> Result<C, ChunkError>
+where
+    I: Iterator<Item = Result<T, String>>,
+    L: Fn(&PathBuf) -> Result<I, String>,
+    M: Fn(BlockIter<T>) -> C,
+{
+    let chunk_error = |error: String| ChunkError { path: path.clone(), error };
+    let stream = load(path).map_err(chunk_error)?;
+
+    let mut failure: Option<String> = None;
+    let result = {
+        let mut items = stream.map_while(|r| r.map_err(|e| failure = Some(e)).ok());
+        map(&mut items)
+    };
+    match failure {
+        Some(e) => Err(chunk_error(e)),
+        None => Ok(result),
+    }
+}
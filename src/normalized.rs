@@ -0,0 +1,373 @@
+use std::{path::Path, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+use solana_program::{
+    pubkey::Pubkey, hash::Hash, clock::UnixTimestamp,
+    instruction::CompiledInstruction, message::{Message, MessageHeader}
+};
+use solana_sdk::{signature::Signature, transaction::Transaction, vote};
+use solana_transaction_status::{
+    ConfirmedBlock, EncodedConfirmedBlock, TransactionStatusMeta, TransactionWithStatusMeta,
+    InnerInstructions, Rewards, UiTransactionEncoding
+};
+
+use crate::{
+    binary_chunk::{
+        ChunkHeader, NORMALIZED_MAGIC, serialize_with_header, deserialize_with_header, to_status_meta
+    },
+    files::SlotData,
+    decode::{DecodedInstruction, decode_instruction},
+    util::log_err_none
+};
+
+
+// a block whose transactions are already decoded & have their account roles resolved,
+// so analyses don't have to decode base64 transactions every time they run
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct NormalizedBlock {
+    pub slot: u64,
+    pub parent_slot: u64,
+    pub blockhash: String,
+    pub previous_blockhash: String,
+    pub block_time: Option<UnixTimestamp>,
+    pub block_height: Option<u64>,
+    pub rewards: Rewards,
+    pub transactions: Vec<NormalizedTx>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct NormalizedTx {
+    pub signatures: Vec<Signature>,
+    pub header: MessageHeader,
+    pub recent_blockhash: Hash,
+    pub accounts: Vec<TxAccount>,
+    pub instructions: Vec<NormalizedInstruction>,
+    // inner instructions live here with their program ids resolved, not in meta.
+    // meta keeps an empty list when they were recorded, & None when they weren't, so the two round-trip apart
+    pub inner_instructions: Vec<NormalizedInnerInstructions>,
+    pub meta: Option<TransactionStatusMeta>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TxAccount {
+    pub pubkey: Pubkey,
+    pub signer: bool,
+    pub writable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct NormalizedInstruction {
+    pub program_id: Pubkey,
+    pub program_id_index: u8,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct NormalizedInnerInstructions {
+    // which of the transaction's top-level instructions these were invoked by
+    pub index: u8,
+    pub instructions: Vec<NormalizedInstruction>,
+}
+
+// what an account is in a transaction, from where its key sits in the message:
+// the fee payer is always first, then the other signers, then the non-signers,
+// each group with its writable keys before its readonly ones
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AccountRole {
+    FeePayer,
+    Signer,             // besides the fee payer, writable or not
+    Writable,           // writable, not a signer
+    Readonly,           // readonly, not a signer
+}
+
+impl AccountRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountRole::FeePayer => "fee_payer",
+            AccountRole::Signer => "signer",
+            AccountRole::Writable => "writable",
+            AccountRole::Readonly => "readonly",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<AccountRole, String> {
+        match name {
+            "fee_payer" | "payer" => Ok(AccountRole::FeePayer),
+            "signer" => Ok(AccountRole::Signer),
+            "writable" => Ok(AccountRole::Writable),
+            "readonly" => Ok(AccountRole::Readonly),
+            other => Err(format!("role '{}' not recognized, use one of: fee_payer, signer, writable, readonly", other))
+        }
+    }
+}
+
+// a log line & the program that was running once it was logged, from the "Program <id> invoke [n]"
+// & "Program <id> success/failed" lines.  invoke lines also say who made the call & at what depth
+pub(crate) struct InvokeLog<'a> {
+    pub line: &'a str,
+    pub program: Option<Pubkey>,
+    pub invoke: Option<(Option<Pubkey>, usize)>,    // (calling program, depth), top-level calls have no caller
+}
+
+fn invoke_logs(logs: &[String]) -> Vec<InvokeLog<'_>> {
+    let mut stack = Vec::<Pubkey>::new();
+    logs.iter().map(|line| {
+        let words: Vec<&str> = line.split(' ').collect();
+        let mut invoke = None;
+        match words.as_slice() {
+            ["Program", id, "invoke", depth] => {
+                if let (Ok(id), Ok(depth)) = (Pubkey::from_str(id), depth.trim_matches(['[', ']']).parse::<usize>()) {
+                    // a new top-level instruction starts over, even if the last one's logs were cut short
+                    if depth <= 1 { stack.clear() }
+                    invoke = Some((stack.last().copied(), depth));
+                    stack.push(id);
+                }
+            },
+            ["Program", _, "success"] | ["Program", _, "failed:", ..] => { stack.pop(); },
+            _ => {},
+        }
+        InvokeLog { line: line.as_str(), program: stack.last().copied(), invoke }
+    }).collect()
+}
+
+// which transactions an analysis looks at, by whether they're validator votes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum VoteFilter {
+    #[default]
+    Include,            // every transaction
+    Exclude,            // only non-vote transactions
+    Only,               // only vote transactions
+}
+
+impl VoteFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VoteFilter::Include => "include",
+            VoteFilter::Exclude => "exclude",
+            VoteFilter::Only => "only",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<VoteFilter, String> {
+        match name {
+            "include" => Ok(VoteFilter::Include),
+            "exclude" => Ok(VoteFilter::Exclude),
+            "only" => Ok(VoteFilter::Only),
+            other => Err(format!("votes '{}' not recognized, use one of: include, exclude, only", other))
+        }
+    }
+
+    pub fn keeps(&self, tx: &NormalizedTx) -> bool {
+        match self {
+            VoteFilter::Include => true,
+            VoteFilter::Exclude => !tx.is_vote(),
+            VoteFilter::Only => tx.is_vote(),
+        }
+    }
+}
+
+impl NormalizedInstruction {
+    // with the core programs' decoders, Ok(None) for any other program
+    pub fn decode(&self) -> Result<Option<DecodedInstruction>, String> {
+        decode_instruction(&self.program_id, &self.data)
+    }
+}
+
+impl NormalizedTx {
+    // by the message header alone, so unlike TxAccount.writable, program ids & sysvars aren't demoted
+    pub fn account_role(&self, index: usize) -> AccountRole {
+        let signed = self.header.num_required_signatures as usize;
+        let unsigned_writable = self.accounts.len()
+            .saturating_sub(self.header.num_readonly_unsigned_accounts as usize);
+
+        if index == 0 { AccountRole::FeePayer }
+        else if index < signed { AccountRole::Signer }
+        else if index < unsigned_writable { AccountRole::Writable }
+        else { AccountRole::Readonly }
+    }
+
+    pub fn fee_payer(&self) -> Option<Pubkey> {
+        self.accounts.first().map(|a| a.pubkey)
+    }
+
+    // transactions without a status meta are counted as successful
+    pub fn failed(&self) -> bool {
+        self.meta.as_ref().is_some_and(|m| m.status.is_err())
+    }
+
+    // a validator's vote:  any of its top-level instructions invoke the Vote program
+    pub fn is_vote(&self) -> bool {
+        self.instructions.iter().any(|ix| vote::program::check_id(&ix.program_id))
+    }
+
+    // every log line with the program that logged it, following the invoke stack
+    pub fn invoke_logs(&self) -> Vec<InvokeLog<'_>> {
+        self.meta.as_ref().and_then(|m| m.log_messages.as_ref()).map(|logs| invoke_logs(logs)).unwrap_or_default()
+    }
+
+    pub fn signers(&self) -> impl Iterator<Item = Pubkey> + '_ {
+        self.accounts.iter().filter(|a| a.signer).map(|a| a.pubkey)
+    }
+
+    fn from_decoded(tx: &Transaction, meta: Option<TransactionStatusMeta>) -> Result<NormalizedTx, String> {
+        let message = &tx.message;
+        let accounts = message.account_keys.iter().enumerate().map(|(i, pubkey)| {
+            TxAccount { pubkey: *pubkey, signer: message.is_signer(i), writable: message.is_writable(i, true) }
+        }).collect();
+
+        let instructions = message.instructions.iter()
+            .map(|ix| normalize_instruction(ix, message))
+            .collect::<Result<_, String>>()?;
+
+        let mut meta = meta;
+        let recorded = meta.as_mut().and_then(|m| m.inner_instructions.as_mut().map(std::mem::take));
+        let inner_instructions = match recorded {
+            Some(inner) => inner.iter().map(|ixs| Ok(NormalizedInnerInstructions {
+                index: ixs.index,
+                instructions: ixs.instructions.iter()
+                    .map(|ix| normalize_instruction(ix, message))
+                    .collect::<Result<_, String>>()?,
+            })).collect::<Result<_, String>>()?,
+            None => vec![],
+        };
+
+        Ok(NormalizedTx {
+            signatures: tx.signatures.clone(),
+            header: message.header.clone(),
+            recent_blockhash: message.recent_blockhash,
+            accounts,
+            instructions,
+            inner_instructions,
+            meta,
+        })
+    }
+
+    fn to_decoded(&self) -> TransactionWithStatusMeta {
+        let message = Message {
+            header: self.header.clone(),
+            account_keys: self.accounts.iter().map(|a| a.pubkey).collect(),
+            recent_blockhash: self.recent_blockhash,
+            instructions: self.instructions.iter().map(compile_instruction).collect(),
+        };
+
+        // chunks normalized before meta kept the empty list have None there, whether or not any were recorded
+        let meta = self.meta.clone().map(|mut meta| {
+            if meta.inner_instructions.is_some() || !self.inner_instructions.is_empty() {
+                meta.inner_instructions = Some(self.inner_instructions.iter().map(|ixs| InnerInstructions {
+                    index: ixs.index,
+                    instructions: ixs.instructions.iter().map(compile_instruction).collect(),
+                }).collect());
+            }
+            meta
+        });
+
+        TransactionWithStatusMeta {
+            transaction: Transaction { signatures: self.signatures.clone(), message },
+            meta
+        }
+    }
+}
+
+fn normalize_instruction(ix: &CompiledInstruction, message: &Message) -> Result<NormalizedInstruction, String> {
+    match message.account_keys.get(ix.program_id_index as usize) {
+        Some(program_id) => Ok(NormalizedInstruction {
+            program_id: *program_id,
+            program_id_index: ix.program_id_index,
+            accounts: ix.accounts.clone(),
+            data: ix.data.clone(),
+        }),
+        None => Err(format!("instruction's program id index {} is out of range", ix.program_id_index)),
+    }
+}
+
+fn compile_instruction(ix: &NormalizedInstruction) -> CompiledInstruction {
+    CompiledInstruction {
+        program_id_index: ix.program_id_index, accounts: ix.accounts.clone(), data: ix.data.clone()
+    }
+}
+
+// a transaction that can't be decoded or normalized fails the whole block, rather than being left out of it
+pub(crate) fn normalize_block(slot: u64, block: &EncodedConfirmedBlock) -> Result<NormalizedBlock, String> {
+    let transactions = block.transactions.iter().enumerate().map(|(i, etx)| {
+        let tx = etx.transaction.decode().ok_or(format!("slot {}, transaction {} didn't decode", slot, i))?;
+        let normalized = etx.meta.as_ref().map(to_status_meta).transpose()
+            .and_then(|meta| NormalizedTx::from_decoded(&tx, meta));
+        normalized.map_err(|e| format!("slot {}, transaction {}:  {}", slot, i, e))
+    }).collect::<Result<_, String>>()?;
+
+    Ok(NormalizedBlock {
+        slot,
+        parent_slot: block.parent_slot,
+        blockhash: block.blockhash.clone(),
+        previous_blockhash: block.previous_blockhash.clone(),
+        block_time: block.block_time,
+        block_height: block.block_height,
+        rewards: block.rewards.clone(),
+        transactions,
+    })
+}
+
+// back to the shape the RPC gave us, for tasks that still work on raw blocks
+pub(crate) fn denormalize_block(block: &NormalizedBlock) -> SlotData {
+    let confirmed = ConfirmedBlock {
+        previous_blockhash: block.previous_blockhash.clone(),
+        blockhash: block.blockhash.clone(),
+        parent_slot: block.parent_slot,
+        transactions: block.transactions.iter().map(|tx| tx.to_decoded()).collect(),
+        rewards: block.rewards.clone(),
+        block_time: block.block_time,
+        block_height: block.block_height,
+    };
+    (block.slot, confirmed.encode(UiTransactionEncoding::Base64))
+}
+
+pub(crate) fn serialize_normalized_chunk(chunk: &[SlotData]) -> Result<Vec<u8>, String> {
+    let blocks: Vec<NormalizedBlock> = chunk.iter()
+        .map(|(slot, ecb)| normalize_block(*slot, ecb))
+        .collect::<Result<_, String>>()?;
+
+    serialize_with_header(&ChunkHeader::new(NORMALIZED_MAGIC, chunk), &blocks).map_err(|e| e.to_string())
+}
+
+fn load_normalized_chunk_file<P: AsRef<Path>>(path: P) -> Option<Vec<NormalizedBlock>> {
+    match deserialize_with_header::<_, Vec<NormalizedBlock>>(path, NORMALIZED_MAGIC) {
+        Ok((_, blocks)) => Some(blocks),
+        Err(e) => log_err_none(&e)
+    }
+}
+
+// normalized chunks loaded as raw blocks, see load_blocks_chunk()
+pub fn load_normalized_chunk_as_raw<P: AsRef<Path>>(path: P) -> Option<Vec<SlotData>> {
+    load_normalized_chunk_file(path).map(|blocks| blocks.iter().map(denormalize_block).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_follow_the_invoke_stack() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs: Vec<String> = vec![
+            format!("Program {} invoke [1]", a),
+            "Program data: x".to_string(),
+            format!("Program {} invoke [2]", b),
+            "Program data: y".to_string(),
+            format!("Program {} success", b),
+            "Program data: z".to_string(),
+            format!("Program {} failed: custom program error: 0x1", a),
+            "Program data: w".to_string(),
+            format!("Program {} invoke [1]", b),
+            format!("Program {} invoke [1]", a),
+        ];
+        let logged = invoke_logs(&logs);
+        let programs: Vec<Option<Pubkey>> = logged.iter().map(|log| log.program).collect();
+        let invokes: Vec<(Option<Pubkey>, usize)> = logged.iter().filter_map(|log| log.invoke).collect();
+
+        assert_eq!(programs, vec![Some(a), Some(a), Some(b), Some(b), Some(a), Some(a), None, None, Some(b), Some(a)]);
+        // a top-level call after one whose logs stop without "success" starts a new stack
+        assert_eq!(invokes, vec![(None, 1), (Some(a), 2), (None, 1), (None, 1)]);
+    }
+}
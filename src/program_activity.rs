@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    normalized::{NormalizedBlock, NormalizedTx}
+};
+
+
+// how much each program is used, by the instructions that invoke it rather than by account keys
+#[derive(Default)]
+pub(crate) struct ProgramActivity {
+    top: usize,
+    programs: HashMap<Pubkey, ProgramStats>,
+    sorted: Vec<(Pubkey, ProgramStats)>,        // most transactions first, filled in by finalize()
+}
+
+#[derive(Clone, Default)]
+struct ProgramStats {
+    txs: u64,                   // transactions invoking the program at least once, directly or by CPI
+    instructions: u64,          // top-level instructions
+    cpi_invocations: u64,       // inner instructions, invoked by another program
+    succeeded: u64,
+    failed: u64,
+    signers: HashSet<Pubkey>,
+}
+
+impl ProgramStats {
+    fn merge(&mut self, other: ProgramStats) {
+        self.txs += other.txs;
+        self.instructions += other.instructions;
+        self.cpi_invocations += other.cpi_invocations;
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.signers.extend(other.signers);
+    }
+}
+
+#[derive(Serialize)]
+struct ProgramRow {
+    program: String,
+    txs: u64,
+    instructions: u64,
+    cpi_invocations: u64,
+    succeeded: u64,
+    failed: u64,
+    distinct_signers: usize,
+}
+
+impl ProgramActivity {
+    pub fn new(top: usize) -> ProgramActivity {
+        ProgramActivity { top, ..Default::default() }
+    }
+}
+
+impl Analyzer for ProgramActivity {
+    fn empty(&self) -> ProgramActivity {
+        ProgramActivity::new(self.top)
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, tx: &NormalizedTx) {
+        let mut invoked = HashSet::<Pubkey>::new();
+        tx.instructions.iter().for_each(|ix| {
+            self.programs.entry(ix.program_id).or_default().instructions += 1;
+            invoked.insert(ix.program_id);
+        });
+        tx.inner_instructions.iter().flat_map(|inner| inner.instructions.iter()).for_each(|ix| {
+            self.programs.entry(ix.program_id).or_default().cpi_invocations += 1;
+            invoked.insert(ix.program_id);
+        });
+
+        let failed = tx.failed();
+        for program in invoked {
+            let stats = self.programs.entry(program).or_default();
+            stats.txs += 1;
+            if failed { stats.failed += 1 } else { stats.succeeded += 1 }
+            stats.signers.extend(tx.signers());
+        }
+    }
+
+    fn merge(&mut self, other: ProgramActivity) {
+        other.programs.into_iter().for_each(|(program, stats)| self.programs.entry(program).or_default().merge(stats));
+    }
+
+    fn finalize(&mut self) {
+        self.sorted = self.programs.iter().map(|(p, s)| (*p, s.clone())).collect();
+        ranked(&mut self.sorted, |(_, s)| s.txs, |a, b| a.0.cmp(&b.0));
+    }
+
+    fn print_report(&self) {
+        println!("programs invoked:  {}", self.sorted.len());
+        println!("    {:<44} {:>10} {:>12} {:>10} {:>10} {:>8} {:>8}",
+                 "program", "txs", "instructions", "cpi", "succeeded", "failed", "signers");
+        self.sorted.iter().take(self.top).for_each(|(program, s)| {
+            println!("    {:<44} {:>10} {:>12} {:>10} {:>10} {:>8} {:>8}",
+                     program.to_string(), s.txs, s.instructions, s.cpi_invocations, s.succeeded, s.failed, s.signers.len());
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        let rows: Vec<ProgramRow> = self.sorted.iter().map(|(program, s)| ProgramRow {
+            program: program.to_string(),
+            txs: s.txs,
+            instructions: s.instructions,
+            cpi_invocations: s.cpi_invocations,
+            succeeded: s.succeeded,
+            failed: s.failed,
+            distinct_signers: s.signers.len(),
+        }).collect();
+        out.write_json("", &rows)
+    }
+}
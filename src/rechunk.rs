@@ -0,0 +1,110 @@
+use std::{fs, path::PathBuf};
+
+use rayon::{iter::{IntoParallelRefIterator, ParallelIterator}, current_num_threads};
+
+use crate::{
+    cli::CliArguments,
+    files::{
+        BLOCKS_DIR, CHUNKED_BLOCKS_DIR, SlotData, ChunkFormat, 
+        chunk_file_paths, load_blocks_chunk, write_slot_file
+    },
+    input_chunk::{BlockGrouper, ChunkBoundary, chunk_boundary_arg, write_grouped},
+    manifest::{update_manifest, check_chunk_order, chunk_slot_ranges},
+    util::{log_err, log_err_none, timer, do_or_die}
+};
+
+
+// the source dir's chunks, in slot order.
+// they can only be re-grouped in order if they don't overlap
+fn ordered_chunk_paths(src_dir: &str) -> Option<Vec<PathBuf>> {
+    if let Err(problems) = check_chunk_order(src_dir) {
+        log_err(&format!("can't re-chunk {}, its chunks overlap or are out of order:", src_dir));
+        problems.iter().for_each(log_err);
+        return None;
+    }
+    Some(chunk_slot_ranges(src_dir).into_iter().map(|(_, _, path)| path).collect())
+}
+
+// re-group every block in the source dir's chunks into new chunks in the dest dir.
+// source chunks are loaded a few at a time in parallel, so the whole dir never has to fit in memory
+pub(crate) fn rechunk(src_dir: &str, dest_dir: &str, boundary: ChunkBoundary, args: &CliArguments) {
+    let src_paths = match ordered_chunk_paths(src_dir) {
+        Some(paths) => paths,
+        None => return,
+    };
+    // keep the source's format unless told otherwise
+    let format = args.chunk_format
+        .unwrap_or_else(|| src_paths.first().map(ChunkFormat::from_path).unwrap_or_default());
+    println!("re-chunking {} chunks in {} to {}, by {:?}", src_paths.len(), src_dir, dest_dir, boundary);
+    println!("chunk file format:  {:?},  compression:  {:?}", format, args.compression);
+
+    let batches = src_paths.chunks(current_num_threads()).map(|batch| {
+        let loaded: Vec<Option<Vec<SlotData>>> = batch.par_iter().map(|path| {
+            load_blocks_chunk(path).or_else(|| log_err_none(format!("chunk load failed, path:  {:?}", path)))
+        }).collect();
+        loaded.into_iter().flatten().flatten().collect::<Vec<SlotData>>()
+    });
+    let grouper = BlockGrouper::new(boundary, args.max_gap);
+    let entries = write_grouped(batches, grouper, dest_dir, format, args.compression);
+
+    println!("chunks written:  {}", entries.len());
+    update_manifest(dest_dir, entries, &[]);
+}
+
+const NO_SRC_EXIT_MSG: &str = "can't proceed without a valid source directory!\nexiting\n";
+
+// handler for the 'rechunk' CLI task
+pub(crate) fn rechunk_cli(args: &CliArguments) {
+    let src_dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    // clap requires --dest for this task
+    let dest_dir = match args.dest.as_deref() {
+        Some(d) => d,
+        None => return log_err("CLI parsing should prevent this branch"),
+    };
+    do_or_die(|| fs::metadata(src_dir), NO_SRC_EXIT_MSG);
+
+    if fs::canonicalize(src_dir).ok() == fs::canonicalize(dest_dir).ok() {
+        return log_err("re-chunking needs a destination directory other than the source");
+    }
+    if let Err(e) = fs::create_dir_all(dest_dir) { return log_err(&e) }
+    if !chunk_slot_ranges(dest_dir).is_empty() {
+        return log_err(&format!("{} already has chunks, re-chunk into an empty directory", dest_dir));
+    }
+
+    let boundary = chunk_boundary_arg(args);
+    let elapsed = timer(|| rechunk(src_dir, dest_dir, boundary, args));
+    println!("done, time:  {:3} seconds", elapsed.as_secs_f32());
+}
+
+// write every block in the source dir's chunks back out to its own slot file, in the sharded layout
+pub(crate) fn unchunk(src_dir: &str, dest_dir: &str, args: &CliArguments) {
+    // order doesn't matter here, a slot already written from an overlapping chunk is just skipped
+    let src_paths = chunk_file_paths(src_dir);
+    println!("splitting {} chunks in {} into slot files in {}", src_paths.len(), src_dir, dest_dir);
+    println!("slot file compression:  {:?}", args.compression);
+
+    let (written, present): (usize, usize) = src_paths.par_iter().map(|path| {
+        match load_blocks_chunk(path) {
+            Some(chunk) => chunk.iter().fold((0, 0), |(written, present), (slot, block)| {
+                match write_slot_file(dest_dir, *slot, block, args.compression) {
+                    Ok(true) => (written + 1, present),
+                    Ok(false) => (written, present + 1),
+                    Err(e) => { log_err(&e); (written, present) }
+                }
+            }),
+            None => { log_err(&format!("chunk load failed, path:  {:?}", path)); (0, 0) }
+        }
+    }).reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    println!("slot files written:  {},  already present:  {}", written, present);
+}
+
+// handler for the 'unchunk' CLI task
+pub(crate) fn unchunk_cli(args: &CliArguments) {
+    let src_dir = args.source.as_deref().unwrap_or(CHUNKED_BLOCKS_DIR);
+    let dest_dir = args.dest.as_deref().unwrap_or(BLOCKS_DIR);
+    do_or_die(|| fs::metadata(src_dir), NO_SRC_EXIT_MSG);
+
+    let elapsed = timer(|| unchunk(src_dir, dest_dir, args));
+    println!("done, time:  {:3} seconds", elapsed.as_secs_f32());
+}
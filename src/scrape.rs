@@ -1,27 +1,30 @@
 use std::{time::Duration, fs, cmp::max};
 use serde::{Serialize, Deserialize};
 
-use crate::{util::{log_err, loop_task, minutes_duration}, client::SolClient, files, cli::CliArguments, scrape};
+use crate::{
+    util::{log_err, loop_task, minutes_duration}, client::SolClient, files, cli::CliArguments, scrape,
+    compression::Compression
+};
 
 
-pub(crate) fn do_scrape(rpc_url: &str) {
+pub(crate) fn do_scrape(rpc_url: &str, compression: Compression) {
     match load_state() {
         Ok(s) => {
             println!("\nloaded previous run's state from file:\n{:?}", s);
-            if let Some(new_state) = scrape_blocks(s, rpc_url) {
+            if let Some(new_state) = scrape_blocks(s, rpc_url, compression) {
                 save_state(new_state); 
             };
         },
         Err(e) => {
             log_err(&e);
             // we can still run ok, so use default
-            scrape_blocks(ScrapeState { last_slot: 0 }, rpc_url);
+            scrape_blocks(ScrapeState { last_slot: 0 }, rpc_url, compression);
         }
     }
 }
 
 // request data of recent blocks from an RPC node, and save them to disk
-fn scrape_blocks(previous_state: ScrapeState, rpc_url: &str) -> Option<ScrapeState> {
+fn scrape_blocks(previous_state: ScrapeState, rpc_url: &str, compression: Compression) -> Option<ScrapeState> {
     println!("using rpc url:  {}\n", rpc_url);
     let mut client = SolClient::get(rpc_url);
 
@@ -44,7 +47,7 @@ fn scrape_blocks(previous_state: ScrapeState, rpc_url: &str) -> Option<ScrapeSta
             match *ecb {
                 Some(b) => {
                     //println!("write range block file:  slot_{}", slot);
-                    files::write_json_encoded_block(*slot, b);
+                    files::write_json_encoded_block(*slot, b, compression);
                 },
                 None => {},
             }
@@ -54,8 +57,8 @@ fn scrape_blocks(previous_state: ScrapeState, rpc_url: &str) -> Option<ScrapeSta
     else { Some(ScrapeState { last_slot: last }) }
 }
 
-pub(crate) fn scrape_loop(duration: Duration, rpc_url: &str) {
-    let task = || { do_scrape(rpc_url) };
+pub(crate) fn scrape_loop(duration: Duration, rpc_url: &str, compression: Compression) {
+    let task = || { do_scrape(rpc_url, compression) };
     loop_task(duration, task);
 }
 
@@ -66,7 +69,7 @@ pub(crate) fn scrape_with_args(cli_args: &CliArguments) {
     match cli_args.rpc.as_ref() {
         Some(rpc) => {
             println!("\nscraping blocks for {} minutes, from RPC node:  {}\n", mins, rpc);
-            scrape::scrape_loop(duration, &rpc);
+            scrape::scrape_loop(duration, &rpc, cli_args.compression);
         },
         None => {
             eprintln!("\nSolana RPC url required, but not provided\n");
@@ -0,0 +1,178 @@
+use std::{fmt::{self, Display, Formatter}, path::PathBuf};
+
+use solana_program::clock::UnixTimestamp;
+
+use crate::{
+    files::{parse_chunk_slots, parse_chunk_time_window},
+    manifest::{ChunkManifest, chunk_paths_in_order},
+    util::log_err
+};
+
+
+// which blocks an analysis should look at, from the --from-slot/--to-slot & --since/--until args.
+// every bound is inclusive, & any left out is open-ended
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct SlotRange {
+    pub from_slot: Option<u64>,
+    pub to_slot: Option<u64>,
+    pub since: Option<UnixTimestamp>,
+    pub until: Option<UnixTimestamp>,
+}
+
+impl SlotRange {
+    pub fn is_unbounded(&self) -> bool {
+        *self == SlotRange::default()
+    }
+
+    fn has_time_bounds(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    // blocks without a block time can't be placed in a time range, so they're left out of one
+    pub fn contains(&self, slot: u64, block_time: Option<UnixTimestamp>) -> bool {
+        let slot_ok = self.from_slot.is_none_or(|from| slot >= from) &&
+                      self.to_slot.is_none_or(|to| slot <= to);
+        if !slot_ok { return false }
+        if !self.has_time_bounds() { return true }
+
+        match block_time {
+            Some(time) => self.since.is_none_or(|since| time >= since) &&
+                          self.until.is_none_or(|until| time <= until),
+            None => false
+        }
+    }
+
+    pub fn overlaps_slots(&self, first: u64, last: u64) -> bool {
+        self.from_slot.is_none_or(|from| last >= from) && self.to_slot.is_none_or(|to| first <= to)
+    }
+
+    // a chunk with no recorded times might still hold blocks in range, so it's kept
+    pub fn overlaps_times(&self, min_time: Option<UnixTimestamp>, max_time: Option<UnixTimestamp>) -> bool {
+        let after_since = match (self.since, max_time) {
+            (Some(since), Some(max)) => max >= since,
+            _ => true
+        };
+        let before_until = match (self.until, min_time) {
+            (Some(until), Some(min)) => min <= until,
+            _ => true
+        };
+        after_since && before_until
+    }
+}
+
+// the chunk files in a dir that might hold blocks in the range.
+// the dir's manifest has slot & time ranges for each chunk, but without one
+// only the slot range & any time window in the chunk's file name can be checked.
+// they're in slot order, which is the order analyses merge their chunks' results in
+pub(crate) fn select_chunk_paths(dir: &str, range: &SlotRange) -> Vec<PathBuf> {
+    let paths = chunk_paths_in_order(dir);
+    if range.is_unbounded() { return paths }
+
+    let manifest = ChunkManifest::load(dir).unwrap_or_else(|e| {
+        log_err(&e);
+        ChunkManifest::default()
+    });
+
+    let total = paths.len();
+    let selected: Vec<PathBuf> = paths.into_iter().filter(|path| {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match manifest.chunks.iter().find(|c| c.file == name) {
+            Some(entry) => range.overlaps_slots(entry.first_slot, entry.last_slot) &&
+                           range.overlaps_times(entry.min_block_time, entry.max_block_time),
+            None => {
+                let slots_overlap = parse_chunk_slots(&name)
+                    .is_none_or(|(first, last)| range.overlaps_slots(first, last));
+                let window = parse_chunk_time_window(&name);
+                slots_overlap && range.overlaps_times(window.map(|w| w.0), window.map(|w| w.1))
+            }
+        }
+    }).collect();
+
+    println!("chunks overlapping {}:  {} of {}", range, selected.len(), total);
+    selected
+}
+
+impl Display for SlotRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "slots {}-{}", fmt_bound(self.from_slot), fmt_bound(self.to_slot))?;
+        if self.has_time_bounds() {
+            write!(f, ", block times {}-{}", fmt_bound(self.since), fmt_bound(self.until))?;
+        }
+        Ok(())
+    }
+}
+
+fn fmt_bound<T: Display>(bound: Option<T>) -> String {
+    bound.map_or("*".to_string(), |b| b.to_string())
+}
+
+// parse a --since / --until value:  unix seconds, or a UTC date like "2021-11-27" or "2021-11-27T14:30:00"
+pub(crate) fn parse_timestamp(input: &str) -> Result<UnixTimestamp, String> {
+    if let Ok(seconds) = input.parse::<UnixTimestamp>() { return Ok(seconds) }
+
+    let err = || format!("time '{}' not recognized, use unix seconds or YYYY-MM-DD[THH:MM[:SS]]", input);
+    let (date, time) = match input.split_once(['T', ' ']) {
+        Some((d, t)) => (d, Some(t)),
+        None => (input, None),
+    };
+
+    let date_parts: Vec<i64> = date.split('-').map(|p| p.parse::<i64>()).collect::<Result<_, _>>().map_err(|_| err())?;
+    let time_parts: Vec<i64> = match time {
+        Some(t) => t.trim_end_matches('Z').split(':')
+            .map(|p| p.parse::<i64>()).collect::<Result<_, _>>().map_err(|_| err())?,
+        None => vec![],
+    };
+    if date_parts.len() != 3 || time_parts.len() > 3 { return Err(err()) }
+
+    let (year, month, day) = (date_parts[0], date_parts[1], date_parts[2]);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) { return Err(err()) }
+    if time_parts.iter().zip([23, 59, 59]).any(|(n, max)| !(0..=max).contains(n)) { return Err(err()) }
+    let seconds_of_day: i64 = time_parts.iter().zip([3600, 60, 1]).map(|(n, unit)| n * unit).sum();
+
+    Ok(days_from_civil(year, month, day) * 86400 + seconds_of_day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 for a proleptic gregorian date, from Howard Hinnant's date algorithms
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc_seconds() {
+        assert_eq!(parse_timestamp("2021-11-27T14:30:00"), Ok(1638023400));
+        assert_eq!(parse_timestamp("2021-11-27"), Ok(1637971200));
+        assert_eq!(parse_timestamp("1638023400"), Ok(1638023400));
+    }
+
+    #[test]
+    fn days_past_the_end_of_the_month_are_rejected() {
+        assert_eq!(parse_timestamp("2020-02-29"), Ok(1582934400));
+        assert_eq!(parse_timestamp("2000-02-29"), Ok(951782400));
+        assert!(parse_timestamp("2021-02-29").is_err());
+        assert!(parse_timestamp("1900-02-29").is_err());
+        assert!(parse_timestamp("2021-02-31").is_err());
+        assert!(parse_timestamp("2021-04-31").is_err());
+        assert!(parse_timestamp("2021-12-31T23:59:59").is_ok());
+    }
+}
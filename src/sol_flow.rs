@@ -0,0 +1,220 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashMap, HashSet}, fs, path::Path, str::FromStr};
+
+use serde::Serialize;
+use solana_program::{pubkey::Pubkey, native_token::LAMPORTS_PER_SOL};
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    normalized::{NormalizedBlock, NormalizedTx}
+};
+
+
+// where SOL went:  each account's net balance change from the transactions' pre & post balances,
+// & the lamports moved in each slot. optionally without fees, or only for a watch list of accounts
+#[derive(Default)]
+pub(crate) struct SolFlow {
+    top: usize,
+    exclude_fees: bool,                         // add each fee back to its payer, so only transfers count
+    watch: Option<HashSet<Pubkey>>,             // only track these accounts
+    accounts: HashMap<Pubkey, AccountFlow>,
+    per_slot: BTreeMap<u64, Moved>,
+    report: Option<FlowReport>,                 // filled in by finalize()
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+struct AccountFlow {
+    net: i64,                   // lamports
+    received: u64,
+    sent: u64,
+    fees: u64,                  // paid as fee payer, part of sent unless fees are excluded
+    txs: u64,                   // transactions changing the balance
+}
+
+impl AccountFlow {
+    fn merge(&mut self, other: AccountFlow) {
+        self.net += other.net;
+        self.received += other.received;
+        self.sent += other.sent;
+        self.fees += other.fees;
+        self.txs += other.txs;
+    }
+}
+
+// lamports into & out of the tracked accounts. with every account tracked, what was sent
+// is what was received plus the fees (unless they're excluded)
+#[derive(Serialize, Clone, Copy, Default)]
+struct Moved {
+    received: u64,
+    sent: u64,
+}
+
+impl Moved {
+    fn merge(&mut self, other: Moved) {
+        self.received += other.received;
+        self.sent += other.sent;
+    }
+}
+
+#[derive(Serialize)]
+struct FlowReport {
+    exclude_fees: bool,
+    watch_list: Option<Vec<String>>,
+    accounts_changed: usize,
+    total: Moved,
+    top_gainers: Vec<AccountRow>,
+    top_losers: Vec<AccountRow>,
+    per_slot: Vec<SlotRow>,
+    #[serde(skip)]
+    all: Vec<AccountRow>,       // written to its own file
+}
+
+#[derive(Serialize, Clone)]
+struct AccountRow {
+    pubkey: String,
+    #[serde(flatten)]
+    flow: AccountFlow,
+}
+
+#[derive(Serialize)]
+struct SlotRow {
+    slot: u64,
+    #[serde(flatten)]
+    moved: Moved,
+}
+
+fn sol(lamports: i64) -> f64 {
+    lamports as f64 / LAMPORTS_PER_SOL as f64
+}
+
+// a watch list is comma separated pubkeys, or a file of them, separated by commas, spaces or lines
+pub(crate) fn parse_watch_list(arg: &str) -> Result<HashSet<Pubkey>, String> {
+    let list = match Path::new(arg).is_file() {
+        true => fs::read_to_string(arg).map_err(|e| format!("{}:  {}", arg, e))?,
+        false => arg.to_string(),
+    };
+    let keys = list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|k| !k.is_empty())
+        .map(|k| Pubkey::from_str(k).map_err(|_| format!("'{}' in the watch list isn't a valid pubkey", k)))
+        .collect::<Result<HashSet<Pubkey>, String>>()?;
+    match keys.is_empty() {
+        true => Err(format!("no pubkeys in the watch list '{}'", arg)),
+        false => Ok(keys),
+    }
+}
+
+impl SolFlow {
+    pub fn new(top: usize, exclude_fees: bool, watch: Option<HashSet<Pubkey>>) -> SolFlow {
+        SolFlow { top, exclude_fees, watch, ..Default::default() }
+    }
+
+    fn tracked(&self, key: &Pubkey) -> bool {
+        self.watch.as_ref().map(|w| w.contains(key)).unwrap_or(true)
+    }
+}
+
+impl Analyzer for SolFlow {
+    fn empty(&self) -> SolFlow {
+        SolFlow::new(self.top, self.exclude_fees, self.watch.clone())
+    }
+
+    fn tx(&mut self, block: &NormalizedBlock, tx: &NormalizedTx) {
+        let meta = match &tx.meta {
+            Some(meta) => meta,
+            None => return,
+        };
+        let mut moved = Moved::default();
+        let balances = meta.pre_balances.iter().zip(meta.post_balances.iter());
+        for (i, (acct, (pre, post))) in tx.accounts.iter().zip(balances).enumerate() {
+            if !self.tracked(&acct.pubkey) { continue }
+            // the fee payer is always the first account
+            let fee = if i == 0 { meta.fee } else { 0 };
+            let mut change = *post as i64 - *pre as i64;
+            if self.exclude_fees { change += fee as i64 }
+            if change == 0 && fee == 0 { continue }
+
+            let flow = self.accounts.entry(acct.pubkey).or_default();
+            flow.net += change;
+            flow.fees += fee;
+            flow.txs += 1;
+            if change > 0 {
+                flow.received += change as u64;
+                moved.received += change as u64;
+            } else {
+                flow.sent += change.unsigned_abs();
+                moved.sent += change.unsigned_abs();
+            }
+        }
+        if moved.received > 0 || moved.sent > 0 {
+            self.per_slot.entry(block.slot).or_default().merge(moved);
+        }
+    }
+
+    fn merge(&mut self, other: SolFlow) {
+        other.accounts.into_iter().for_each(|(key, flow)| self.accounts.entry(key).or_default().merge(flow));
+        other.per_slot.into_iter().for_each(|(slot, moved)| self.per_slot.entry(slot).or_default().merge(moved));
+    }
+
+    fn finalize(&mut self) {
+        // biggest gain first
+        let mut all: Vec<AccountRow> = self.accounts.iter()
+            .map(|(key, flow)| AccountRow { pubkey: key.to_string(), flow: *flow })
+            .collect();
+        ranked(&mut all, |a| a.flow.net, |a, b| a.pubkey.cmp(&b.pubkey));
+
+        let top_gainers = all.iter().filter(|a| a.flow.net > 0).take(self.top).cloned().collect();
+        let mut top_losers: Vec<AccountRow> = all.iter().rev().filter(|a| a.flow.net < 0).take(self.top).cloned().collect();
+        ranked(&mut top_losers, |a| Reverse(a.flow.net), |a, b| a.pubkey.cmp(&b.pubkey));
+
+        let mut total = Moved::default();
+        self.per_slot.values().for_each(|moved| total.merge(*moved));
+        let mut watch_list: Option<Vec<String>> = self.watch.as_ref().map(|w| w.iter().map(|k| k.to_string()).collect());
+        if let Some(list) = watch_list.as_mut() { list.sort() }
+
+        self.report = Some(FlowReport {
+            exclude_fees: self.exclude_fees,
+            watch_list,
+            accounts_changed: all.len(),
+            total,
+            top_gainers,
+            top_losers,
+            per_slot: self.per_slot.iter().map(|(slot, moved)| SlotRow { slot: *slot, moved: *moved }).collect(),
+            all,
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        let fees = if report.exclude_fees { "excluding fees" } else { "including fees" };
+        match &report.watch_list {
+            Some(list) => println!("balance changes of {} watched accounts, {}", list.len(), fees),
+            None => println!("balance changes of every account, {}", fees),
+        }
+        println!("accounts changed:  {},  SOL received:  {:.6},  SOL sent:  {:.6},  slots:  {}", report.accounts_changed,
+                 sol(report.total.received as i64), sol(report.total.sent as i64), report.per_slot.len());
+
+        let print_rows = |title: &str, rows: &[AccountRow]| {
+            println!("\n    {:<44} {:>18} {:>16} {:>16} {:>8}", title, "net SOL", "received", "sent", "txs");
+            rows.iter().for_each(|a| {
+                println!("    {:<44} {:>18.6} {:>16.6} {:>16.6} {:>8}", a.pubkey, sol(a.flow.net),
+                         sol(a.flow.received as i64), sol(a.flow.sent as i64), a.flow.txs);
+            });
+        };
+        print_rows("top gainers", &report.top_gainers);
+        print_rows("top losers", &report.top_losers);
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let mut suffix = String::new();
+        if self.watch.is_some() { suffix.push_str("_watched") }
+        if self.exclude_fees { suffix.push_str("_no_fees") }
+        out.write_json(&suffix, report)?;
+        out.write_json(&format!("{}_accounts", suffix), &report.all)
+    }
+}
@@ -5,9 +5,18 @@ pub(crate) const SCRAPE_BLOCKS_TASK: &str = "scrape_blocks";
 pub(crate) const COMPARE_BLOCK_LOADS_TASK: &str = "cmp_block_loads";
 pub(crate) const BLOCK_SAMPLE_TASK: &str = "block_sample";
 pub(crate) const GET_BLOCK_PROD_TASK: &str = "get_prod";
+pub(crate) const MIGRATE_BLOCKS_TASK: &str = "migrate_blocks";
+pub(crate) const CONVERT_CHUNKS_TASK: &str = "convert_chunks";
+pub(crate) const VERIFY_CHUNKS_TASK: &str = "verify_chunks";
+pub(crate) const RECHUNK_TASK: &str = "rechunk";
+pub(crate) const UNCHUNK_TASK: &str = "unchunk";
+pub(crate) const COMPACT_BLOCKS_TASK: &str = "compact_blocks";
+pub(crate) const ANALYZE_TASK: &str = "analyze";
+pub(crate) const DUMP_TXS_TASK: &str = "dump_txs";
 
-pub(crate) static TASK_NAMES: [&str; 7] = [
+pub(crate) static TASK_NAMES: [&str; 15] = [
     CHUNK_BLOCKS_TASK, COUNT_KEY_TXS_TASK, MEAN_FILE_SIZE_TASK,
     SCRAPE_BLOCKS_TASK, COMPARE_BLOCK_LOADS_TASK, BLOCK_SAMPLE_TASK,
-    GET_BLOCK_PROD_TASK
+    GET_BLOCK_PROD_TASK, MIGRATE_BLOCKS_TASK, CONVERT_CHUNKS_TASK, VERIFY_CHUNKS_TASK,
+    RECHUNK_TASK, UNCHUNK_TASK, COMPACT_BLOCKS_TASK, ANALYZE_TASK, DUMP_TXS_TASK
 ];
\ No newline at end of file
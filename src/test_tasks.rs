@@ -1,16 +1,33 @@
-use std::{fs::{self, read_dir}, path::{PathBuf}};
+use std::{fs::read_dir, path::{PathBuf}};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    analyze::process_block_stream, client::SolClient,
-    files::{test_block_loads_buf, CHUNKED_BLOCKS_DIR, dir_file_paths, dir_size_stats}, 
-    util::{log_err, timer, ok_or_die} 
+    analyze::process_block_stream, client::SolClient, compression::Compression,
+    slot_range::{SlotRange, select_chunk_paths}, manifest::require_chunk_order, normalized::VoteFilter,
+    files::{
+        test_block_loads_buf, CHUNKED_BLOCKS_DIR, chunk_file_paths, dir_size_stats, 
+        get_file_size, load_blocks_chunk, ChunkFormat
+    }, 
+    util::{log_err, timer, ok_or_die, MEGABYTE} 
 };
 
 
+struct LoadPerf {
+    dir: String,
+    format: ChunkFormat,
+    compression: Compression,
+    disk_bytes: usize,
+    load_seconds: f32,
+    process_seconds: f32,
+}
+
 // load multiple folders of files, containing the same source data 
-// grouped into different size chunks, compare how performance varies with size
+// grouped into different size chunks (and / or compression settings), 
+// compare how performance varies with size & compression
 pub(crate) fn load_perf_by_size(chunked_data_dir: &str) {
     let dir = ok_or_die(|| read_dir(chunked_data_dir));
+    let mut results = Vec::<LoadPerf>::new();
 
     dir.into_iter().for_each(|dir_entry| {
         match dir_entry {
@@ -21,29 +38,63 @@ pub(crate) fn load_perf_by_size(chunked_data_dir: &str) {
                     eprintln!("directory {} not found!", path_str);
                 }
 
-                println!("loading data dir:  {}", path_str);
+                if !path.is_dir() { return }
+                let paths = chunk_file_paths(&path);
+                // chunk dirs are written with one format & compression, so the first file tells us
+                let format = paths.first().map(ChunkFormat::from_path).unwrap_or_default();
+                let compression = paths.first().map(Compression::from_path).unwrap_or_default();
+                let disk_bytes: usize = paths.par_iter().map(get_file_size).sum();
+
+                println!("loading data dir:  {}  ({:?}, {}, {} mb on disk)", 
+                         path_str, format, compression.name(), disk_bytes / MEGABYTE);
+
+                // time just the decompress + parse, then the whole analysis
+                let load_time = timer(|| {
+                    let block_count: usize = paths.par_iter()
+                        .filter_map(load_blocks_chunk)
+                        .map(|chunk| chunk.len())
+                        .sum();
+                    println!("blocks loaded:  {}", block_count);
+                });
                 let elapsed = timer(|| { 
                     test_block_loads_buf(&path); 
                 });
                 println!("finished load & process in {:3} seconds\n", elapsed.as_secs_f32());
+
+                results.push(LoadPerf { 
+                    dir: path_str.to_string(), format, compression, disk_bytes,
+                    load_seconds: load_time.as_secs_f32(), process_seconds: elapsed.as_secs_f32()
+                });
             },
             Err(e) => log_err(&e),
         }
     });
+
+    print_load_perf(&mut results);
 }
 
-pub(crate) fn test_block_loads(chunked_blocks_dir: &str) {
+fn print_load_perf(results: &mut [LoadPerf]) {
+    results.sort_by(|a, b| a.load_seconds.total_cmp(&b.load_seconds));
+
+    println!("{:<40} {:>8} {:>12} {:>10} {:>10} {:>10} {:>10}", 
+             "dir", "format", "compression", "disk mb", "load s", "mb/s", "process s");
+    results.iter().for_each(|r| {
+        let disk_mb = r.disk_bytes as f32 / MEGABYTE as f32;
+        println!("{:<40} {:>8} {:>12} {:>10.1} {:>10.3} {:>10.1} {:>10.3}", 
+                 r.dir, format!("{:?}", r.format), r.compression.name(), disk_mb, r.load_seconds, 
+                 disk_mb / r.load_seconds, r.process_seconds);
+    });
+    println!();
+}
+
+pub(crate) fn test_block_loads(chunked_blocks_dir: &str, range: &SlotRange, threads: Option<usize>, votes: VoteFilter) {
     let mut dir = chunked_blocks_dir;
     if dir.is_empty() { dir = CHUNKED_BLOCKS_DIR }
 
     println!("\nloading + processing chunked Solana block data from {}", dir);
-    match fs::read_dir(dir) {
-        Ok(rd) => {
-            let paths = dir_file_paths(rd);
-            process_block_stream(paths.as_slice());
-        },
-        Err(e) => log_err(&e)
-    };
+    if !require_chunk_order(dir) { return }
+    let paths = select_chunk_paths(dir, range);
+    process_block_stream(paths.as_slice(), range, threads, votes);
 }
 
 // just see if the average file size code runs
@@ -0,0 +1,229 @@
+use std::{collections::{BTreeMap, HashMap}, str::FromStr};
+
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+use solana_transaction_status::TransactionTokenBalance;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    normalized::{NormalizedBlock, NormalizedTx}
+};
+
+
+// where SPL tokens went:  per-mint transfer volume, holders & top senders/receivers,
+// from the transactions' pre & post token balances
+#[derive(Default)]
+pub(crate) struct TokenFlow {
+    top: usize,
+    mints: HashMap<Pubkey, MintFlow>,
+    report: Option<TokenReport>,            // filled in by finalize()
+}
+
+#[derive(Default)]
+struct MintFlow {
+    decimals: u8,
+    txs: u64,                               // transactions changing any balance of the mint
+    volume: u128,                           // raw amounts received, so each transfer counts once
+    owners: HashMap<Pubkey, OwnerFlow>,     // holders whose balance changed
+}
+
+impl MintFlow {
+    fn merge(&mut self, other: MintFlow) {
+        self.decimals = other.decimals;
+        self.txs += other.txs;
+        self.volume += other.volume;
+        other.owners.into_iter().for_each(|(owner, flow)| self.owners.entry(owner).or_default().merge(flow));
+    }
+}
+
+// raw token amounts, not adjusted for the mint's decimals
+#[derive(Serialize, Clone, Copy, Default)]
+struct OwnerFlow {
+    net: i128,
+    received: u128,
+    sent: u128,
+}
+
+impl OwnerFlow {
+    fn add(&mut self, change: i128) {
+        self.net += change;
+        if change > 0 { self.received += change as u128 } else { self.sent += change.unsigned_abs() }
+    }
+
+    fn merge(&mut self, other: OwnerFlow) {
+        self.net += other.net;
+        self.received += other.received;
+        self.sent += other.sent;
+    }
+}
+
+#[derive(Serialize)]
+struct TokenReport {
+    mints: Vec<MintRow>,                    // most transactions first
+    #[serde(skip)]
+    owners: Vec<OwnerRow>,                  // written to their own file
+}
+
+#[derive(Serialize)]
+struct MintRow {
+    mint: String,
+    decimals: u8,
+    txs: u64,
+    volume: u128,
+    ui_volume: f64,
+    holders: usize,
+    top_senders: Vec<HolderAmount>,
+    top_receivers: Vec<HolderAmount>,
+}
+
+#[derive(Serialize)]
+struct HolderAmount {
+    owner: String,
+    amount: u128,
+}
+
+#[derive(Serialize)]
+struct OwnerRow {
+    owner: String,
+    mints: Vec<OwnerMintRow>,
+}
+
+#[derive(Serialize)]
+struct OwnerMintRow {
+    mint: String,
+    #[serde(flatten)]
+    flow: OwnerFlow,
+}
+
+// a token account's balance on one side of a transaction
+struct TokenBalance {
+    mint: Pubkey,
+    owner: Pubkey,
+    decimals: u8,
+    amount: u64,
+}
+
+// by account index. balances with no owner recorded are held by the token account itself
+fn token_balances(tx: &NormalizedTx, balances: &Option<Vec<TransactionTokenBalance>>) -> BTreeMap<u8, TokenBalance> {
+    balances.iter().flatten().filter_map(|b| {
+        let account = tx.accounts.get(b.account_index as usize)?.pubkey;
+        let owner = match b.owner.is_empty() {
+            true => account,
+            false => Pubkey::from_str(&b.owner).ok()?,
+        };
+        Some((b.account_index, TokenBalance {
+            mint: Pubkey::from_str(&b.mint).ok()?,
+            owner,
+            decimals: b.ui_token_amount.decimals,
+            amount: b.ui_token_amount.amount.parse().ok()?,
+        }))
+    }).collect()
+}
+
+fn ui_amount(amount: u128, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+// the largest amounts first
+fn top_holders(owners: &HashMap<Pubkey, OwnerFlow>, amount: fn(&OwnerFlow) -> u128, limit: usize) -> Vec<HolderAmount> {
+    let mut sorted: Vec<(&Pubkey, u128)> = owners.iter().map(|(o, f)| (o, amount(f))).filter(|(_, a)| *a > 0).collect();
+    ranked(&mut sorted, |(_, amount)| *amount, |a, b| a.0.cmp(b.0));
+    sorted.into_iter().take(limit).map(|(owner, amount)| HolderAmount { owner: owner.to_string(), amount }).collect()
+}
+
+impl TokenFlow {
+    pub fn new(top: usize) -> TokenFlow {
+        TokenFlow { top, ..Default::default() }
+    }
+}
+
+impl Analyzer for TokenFlow {
+    fn empty(&self) -> TokenFlow {
+        TokenFlow::new(self.top)
+    }
+
+    fn tx(&mut self, _block: &NormalizedBlock, tx: &NormalizedTx) {
+        let meta = match &tx.meta {
+            Some(meta) => meta,
+            None => return,
+        };
+        let pre = token_balances(tx, &meta.pre_token_balances);
+        let post = token_balances(tx, &meta.post_token_balances);
+
+        // accounts missing on one side were opened or closed by the transaction, so held nothing there
+        let mut changed_mints = Vec::<Pubkey>::new();
+        let indexes: Vec<&u8> = pre.keys().chain(post.keys().filter(|i| !pre.contains_key(i))).collect();
+        for index in indexes {
+            let (before, after) = (pre.get(index), post.get(index));
+            let balance = match after.or(before) {
+                Some(b) => b,
+                None => continue,
+            };
+            let change = after.map(|b| b.amount as i128).unwrap_or(0) - before.map(|b| b.amount as i128).unwrap_or(0);
+            if change == 0 { continue }
+
+            let mint = self.mints.entry(balance.mint).or_default();
+            mint.decimals = balance.decimals;
+            if change > 0 { mint.volume += change as u128 }
+            mint.owners.entry(balance.owner).or_default().add(change);
+            if !changed_mints.contains(&balance.mint) { changed_mints.push(balance.mint) }
+        }
+        changed_mints.iter().for_each(|m| self.mints.entry(*m).or_default().txs += 1);
+    }
+
+    fn merge(&mut self, other: TokenFlow) {
+        other.mints.into_iter().for_each(|(mint, flow)| self.mints.entry(mint).or_default().merge(flow));
+    }
+
+    fn finalize(&mut self) {
+        let mut mints: Vec<MintRow> = self.mints.iter().map(|(mint, flow)| MintRow {
+            mint: mint.to_string(),
+            decimals: flow.decimals,
+            txs: flow.txs,
+            volume: flow.volume,
+            ui_volume: ui_amount(flow.volume, flow.decimals),
+            holders: flow.owners.len(),
+            top_senders: top_holders(&flow.owners, |f| f.sent, self.top),
+            top_receivers: top_holders(&flow.owners, |f| f.received, self.top),
+        }).collect();
+        ranked(&mut mints, |m| (m.txs, m.volume), |a, b| a.mint.cmp(&b.mint));
+
+        let mut by_owner = BTreeMap::<String, Vec<OwnerMintRow>>::new();
+        self.mints.iter().for_each(|(mint, flow)| flow.owners.iter().for_each(|(owner, owner_flow)| {
+            by_owner.entry(owner.to_string()).or_default().push(OwnerMintRow { mint: mint.to_string(), flow: *owner_flow });
+        }));
+        let owners = by_owner.into_iter().map(|(owner, mut mints)| {
+            mints.sort_by(|a, b| a.mint.cmp(&b.mint));
+            OwnerRow { owner, mints }
+        }).collect();
+
+        self.report = Some(TokenReport { mints, owners });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        println!("mints with balance changes:  {},  holders:  {}", report.mints.len(), report.owners.len());
+        println!("\n    {:<44} {:>10} {:>22} {:>10}", "most active mints", "txs", "volume", "holders");
+        report.mints.iter().take(self.top).for_each(|m| {
+            println!("    {:<44} {:>10} {:>22.6} {:>10}", m.mint, m.txs, m.ui_volume, m.holders);
+            let print_holders = |label: &str, holders: &[HolderAmount]| holders.iter().take(3).for_each(|h| {
+                println!("        {:<9} {:<44} {:>22.6}", label, h.owner, ui_amount(h.amount, m.decimals));
+            });
+            print_holders("sent", &m.top_senders);
+            print_holders("received", &m.top_receivers);
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        match &self.report {
+            Some(report) => {
+                out.write_json("", &report.mints)?;
+                out.write_json("_owners", &report.owners)
+            },
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::transaction::TransactionError;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, ranked},
+    normalized::{NormalizedBlock, NormalizedTx},
+    util::variant_name
+};
+
+
+// why transactions failed:  each error's kind, the program whose instruction failed,
+// & failure rates overall, per program & per window of slots
+#[derive(Default)]
+pub(crate) struct ErrorAnalysis {
+    top: usize,
+    slot_window: u64,
+    total: TxOutcomes,
+    kinds: HashMap<String, u64>,                    // error kind, e.g. "InstructionError::Custom" -> count
+    programs: HashMap<Pubkey, ProgramOutcomes>,
+    windows: BTreeMap<u64, TxOutcomes>,             // by the window's first slot
+    custom_codes: HashMap<(Pubkey, u32), u64>,      // (failing program, custom error code) -> count
+    report: Option<ErrorReport>,                    // filled in by finalize()
+}
+
+#[derive(Serialize, Clone, Copy, Default)]
+struct TxOutcomes {
+    txs: u64,
+    failed: u64,
+    failure_rate: f64,              // only worked out by finalize()
+}
+
+impl TxOutcomes {
+    fn add(&mut self, failed: bool) {
+        self.txs += 1;
+        if failed { self.failed += 1 }
+    }
+
+    fn merge(&mut self, other: TxOutcomes) {
+        self.txs += other.txs;
+        self.failed += other.failed;
+    }
+
+    fn with_rate(mut self) -> TxOutcomes {
+        self.failure_rate = if self.txs == 0 { 0.0 } else { self.failed as f64 / self.txs as f64 };
+        self
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ProgramOutcomes {
+    invoked: TxOutcomes,            // transactions invoking the program at the top level
+    failed_in: u64,                 // failures in one of the program's own instructions
+}
+
+#[derive(Serialize)]
+struct ErrorReport {
+    total: TxOutcomes,
+    kinds: Vec<ErrorKindCount>,
+    programs: Vec<ProgramErrors>,
+    slot_window: u64,
+    windows: Vec<WindowErrors>,
+}
+
+#[derive(Serialize)]
+struct ErrorKindCount {
+    kind: String,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct ProgramErrors {
+    program: String,
+    #[serde(flatten)]
+    invoked: TxOutcomes,
+    failed_in_program: u64,
+    top_custom_codes: Vec<CustomCodeCount>,
+}
+
+#[derive(Serialize)]
+struct CustomCodeCount {
+    code: u32,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct WindowErrors {
+    first_slot: u64,
+    #[serde(flatten)]
+    outcomes: TxOutcomes,
+}
+
+// the error's variant, plus the instruction error's for a failed instruction
+fn error_kind(err: &TransactionError) -> String {
+    match err {
+        TransactionError::InstructionError(_, ix_err) => format!("InstructionError::{}", variant_name(ix_err)),
+        other => variant_name(other),
+    }
+}
+
+impl ErrorAnalysis {
+    pub fn new(top: usize, slot_window: u64) -> ErrorAnalysis {
+        ErrorAnalysis { top, slot_window: slot_window.max(1), ..Default::default() }
+    }
+}
+
+impl Analyzer for ErrorAnalysis {
+    fn empty(&self) -> ErrorAnalysis {
+        ErrorAnalysis::new(self.top, self.slot_window)
+    }
+
+    fn tx(&mut self, block: &NormalizedBlock, tx: &NormalizedTx) {
+        let err = tx.meta.as_ref().and_then(|m| m.status.clone().err());
+        let failed = err.is_some();
+
+        self.total.add(failed);
+        let window = block.slot - block.slot % self.slot_window;
+        self.windows.entry(window).or_default().add(failed);
+        let programs: HashSet<Pubkey> = tx.instructions.iter().map(|ix| ix.program_id).collect();
+        programs.into_iter().for_each(|p| self.programs.entry(p).or_default().invoked.add(failed));
+
+        let err = match err {
+            Some(e) => e,
+            None => return,
+        };
+        *self.kinds.entry(error_kind(&err)).or_insert(0) += 1;
+
+        if let TransactionError::InstructionError(index, ix_err) = err {
+            let program = match tx.instructions.get(index as usize) {
+                Some(ix) => ix.program_id,
+                None => return,
+            };
+            self.programs.entry(program).or_default().failed_in += 1;
+            if let InstructionError::Custom(code) = ix_err {
+                *self.custom_codes.entry((program, code)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: ErrorAnalysis) {
+        self.total.merge(other.total);
+        other.kinds.into_iter().for_each(|(kind, count)| *self.kinds.entry(kind).or_insert(0) += count);
+        other.programs.into_iter().for_each(|(program, outcomes)| {
+            let mine = self.programs.entry(program).or_default();
+            mine.invoked.merge(outcomes.invoked);
+            mine.failed_in += outcomes.failed_in;
+        });
+        other.windows.into_iter().for_each(|(window, outcomes)| self.windows.entry(window).or_default().merge(outcomes));
+        other.custom_codes.into_iter().for_each(|(key, count)| *self.custom_codes.entry(key).or_insert(0) += count);
+    }
+
+    fn finalize(&mut self) {
+        let mut kinds: Vec<ErrorKindCount> = self.kinds.iter()
+            .map(|(kind, count)| ErrorKindCount { kind: kind.clone(), count: *count })
+            .collect();
+        ranked(&mut kinds, |k| k.count, |a, b| a.kind.cmp(&b.kind));
+
+        let mut programs: Vec<ProgramErrors> = self.programs.iter().map(|(program, outcomes)| {
+            let mut codes: Vec<CustomCodeCount> = self.custom_codes.iter()
+                .filter(|((p, _), _)| p == program)
+                .map(|((_, code), count)| CustomCodeCount { code: *code, count: *count })
+                .collect();
+            ranked(&mut codes, |c| c.count, |a, b| a.code.cmp(&b.code));
+            codes.truncate(self.top);
+
+            ProgramErrors {
+                program: program.to_string(),
+                invoked: outcomes.invoked.with_rate(),
+                failed_in_program: outcomes.failed_in,
+                top_custom_codes: codes,
+            }
+        }).collect();
+        ranked(&mut programs, |p| p.invoked.failed, |a, b| a.program.cmp(&b.program));
+
+        self.report = Some(ErrorReport {
+            total: self.total.with_rate(),
+            kinds,
+            programs,
+            slot_window: self.slot_window,
+            windows: self.windows.iter()
+                .map(|(first_slot, outcomes)| WindowErrors { first_slot: *first_slot, outcomes: outcomes.with_rate() })
+                .collect(),
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        println!("transactions:  {},  failed:  {},  failure rate:  {:.2}%",
+                 report.total.txs, report.total.failed, report.total.failure_rate * 100.0);
+
+        println!("\n    {:<48} {:>10}", "error", "count");
+        report.kinds.iter().take(self.top).for_each(|k| println!("    {:<48} {:>10}", k.kind, k.count));
+
+        println!("\n    {:<44} {:>10} {:>10} {:>8} {:>12}  top custom codes", "program", "txs", "failed", "rate", "failed in it");
+        report.programs.iter().take(self.top).for_each(|p| {
+            let codes: Vec<String> = p.top_custom_codes.iter().take(3).map(|c| format!("{} x{}", c.code, c.count)).collect();
+            println!("    {:<44} {:>10} {:>10} {:>7.2}% {:>12}  {}", p.program, p.invoked.txs, p.invoked.failed,
+                     p.invoked.failure_rate * 100.0, p.failed_in_program, codes.join(", "));
+        });
+
+        // every window is in the output file, only the worst are printed
+        let mut worst: Vec<&WindowErrors> = report.windows.iter().filter(|w| w.outcomes.failed > 0).collect();
+        worst.sort_by(|a, b| b.outcomes.failure_rate.total_cmp(&a.outcomes.failure_rate).then(a.first_slot.cmp(&b.first_slot)));
+        println!("\n    {:<20} {:>10} {:>10} {:>8}", format!("worst {} slot windows", report.slot_window), "txs", "failed", "rate");
+        worst.iter().take(self.top).for_each(|w| {
+            println!("    {:<20} {:>10} {:>10} {:>7.2}%", w.first_slot, w.outcomes.txs, w.outcomes.failed, w.outcomes.failure_rate * 100.0);
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        match &self.report {
+            Some(report) => out.write_json("", report),
+            None => Ok(()),
+        }
+    }
+}
@@ -4,6 +4,21 @@ use std::{time::{Duration, Instant}, fmt::{Debug, Display}, process::exit};
 pub(crate) const PATH_SEP: char = std::path::MAIN_SEPARATOR;
 pub(crate) const MEGABYTE: usize = 1024 * 1024;
 
+// an enum value's variant, from its Debug output:  "TransferChecked { amount: 5 }" -> "TransferChecked"
+pub(crate) fn variant_name<T: Debug>(value: &T) -> String {
+    format!("{:?}", value).split(['(', ' ', '{']).next().unwrap_or_default().to_string()
+}
+
+// "TransferChecked" -> "transfer_checked", "amountIn" -> "amount_in"
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    name.chars().enumerate().for_each(|(i, c)| {
+        if c.is_uppercase() && i > 0 { snake.push('_') }
+        snake.push(c.to_ascii_lowercase());
+    });
+    snake
+}
+
 pub fn loop_task<F: Fn() -> ()>(total_time: Duration, loop_fn: F) {
     let start = Instant::now();
     let end = start + total_time;
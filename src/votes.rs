@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::Serialize;
+use solana_program::pubkey::Pubkey;
+use solana_vote_program::vote_instruction::VoteInstruction;
+
+use crate::{
+    analyzer::{Analyzer, AnalysisOutput, Percentile, percentiles, ranked},
+    decode::DecodedInstruction,
+    normalized::{NormalizedBlock, NormalizedTx}
+};
+
+
+// validators' votes, apart from everything else:  how many transactions are votes, the votes each validator's
+// vote account made, how many slots after the slot voted on they landed, & which validators missed each window
+#[derive(Default)]
+pub(crate) struct VoteAnalysis {
+    top: usize,
+    slot_window: u64,
+    txs: u64,
+    vote_txs: u64,
+    validators: HashMap<Pubkey, ValidatorVotes>,        // by vote account
+    // slots between the latest slot a vote was for & the slot it was included in -> successful votes
+    latency: BTreeMap<u64, u64>,
+    windows: BTreeMap<u64, HashSet<Pubkey>>,            // every window's first slot -> vote accounts with a successful vote in it
+    report: Option<VoteReport>,                         // filled in by finalize()
+}
+
+#[derive(Default)]
+struct ValidatorVotes {
+    vote_txs: u64,
+    votes: u64,                     // vote instructions
+    failed: u64,
+    slots_voted: u64,
+    latency_sum: u64,               // over successful votes
+    authorities: BTreeSet<Pubkey>,  // signing as the vote authority, usually the validator's identity
+}
+
+impl ValidatorVotes {
+    fn merge(&mut self, other: ValidatorVotes) {
+        self.vote_txs += other.vote_txs;
+        self.votes += other.votes;
+        self.failed += other.failed;
+        self.slots_voted += other.slots_voted;
+        self.latency_sum += other.latency_sum;
+        self.authorities.extend(other.authorities);
+    }
+}
+
+#[derive(Serialize)]
+struct VoteReport {
+    txs: u64,
+    vote_txs: u64,
+    non_vote_txs: u64,
+    vote_share: f64,
+    latency_percentiles: Vec<Percentile>,
+    latency: Vec<LatencyCount>,
+    validators: Vec<ValidatorRow>,
+    slot_window: u64,
+    windows: Vec<WindowVoters>,
+}
+
+#[derive(Serialize)]
+struct LatencyCount {
+    slots: u64,
+    votes: u64,
+}
+
+#[derive(Serialize)]
+struct ValidatorRow {
+    vote_account: String,
+    authorities: Vec<String>,
+    vote_txs: u64,
+    votes: u64,
+    failed: u64,
+    slots_voted: u64,
+    mean_latency: f64,
+    windows_missed: usize,
+}
+
+#[derive(Serialize)]
+struct WindowVoters {
+    first_slot: u64,
+    voters: usize,
+    missing: Vec<String>,           // vote accounts that voted in other windows but not this one
+}
+
+impl VoteAnalysis {
+    pub fn new(top: usize, slot_window: u64) -> VoteAnalysis {
+        VoteAnalysis { top, slot_window: slot_window.max(1), ..Default::default() }
+    }
+}
+
+impl Analyzer for VoteAnalysis {
+    fn empty(&self) -> VoteAnalysis {
+        VoteAnalysis::new(self.top, self.slot_window)
+    }
+
+    fn block(&mut self, block: &NormalizedBlock) {
+        // every window seen is kept, even with no successful votes, so it shows everyone as missing
+        self.windows.entry(block.slot - block.slot % self.slot_window).or_default();
+        block.transactions.iter().for_each(|tx| self.tx(block, tx));
+    }
+
+    fn tx(&mut self, block: &NormalizedBlock, tx: &NormalizedTx) {
+        self.txs += 1;
+        if !tx.is_vote() { return }
+        self.vote_txs += 1;
+        let failed = tx.failed();
+
+        let mut vote_accounts = HashSet::<Pubkey>::new();
+        for ix in tx.instructions.iter() {
+            // only votes are counted, not the Vote program's other instructions
+            let vote = match ix.decode() {
+                Ok(Some(DecodedInstruction::Vote(VoteInstruction::Vote(vote)))) => vote,
+                Ok(Some(DecodedInstruction::Vote(VoteInstruction::VoteSwitch(vote, _)))) => vote,
+                _ => continue,
+            };
+            let account = |i: usize| ix.accounts.get(i).and_then(|a| tx.accounts.get(*a as usize)).map(|a| a.pubkey);
+            let vote_account = match account(0) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let validator = self.validators.entry(vote_account).or_default();
+            validator.votes += 1;
+            validator.slots_voted += vote.slots.len() as u64;
+            if let Some(authority) = account(3) { validator.authorities.insert(authority); }
+            vote_accounts.insert(vote_account);
+            if failed {
+                validator.failed += 1;
+                continue;
+            }
+
+            if let Some(latest) = vote.slots.iter().max() {
+                let latency = block.slot.saturating_sub(*latest);
+                validator.latency_sum += latency;
+                *self.latency.entry(latency).or_insert(0) += 1;
+            }
+            let window = block.slot - block.slot % self.slot_window;
+            self.windows.entry(window).or_default().insert(vote_account);
+        }
+        vote_accounts.iter().for_each(|key| self.validators.entry(*key).or_default().vote_txs += 1);
+    }
+
+    fn merge(&mut self, other: VoteAnalysis) {
+        self.txs += other.txs;
+        self.vote_txs += other.vote_txs;
+        other.validators.into_iter().for_each(|(key, votes)| self.validators.entry(key).or_default().merge(votes));
+        other.latency.into_iter().for_each(|(latency, count)| *self.latency.entry(latency).or_insert(0) += count);
+        other.windows.into_iter().for_each(|(window, voters)| self.windows.entry(window).or_default().extend(voters));
+    }
+
+    fn finalize(&mut self) {
+        // every validator with a successful vote is expected to vote in every window
+        let expected: BTreeSet<Pubkey> = self.windows.values().flatten().copied().collect();
+        let mut windows_missed = HashMap::<Pubkey, usize>::new();
+        let windows: Vec<WindowVoters> = self.windows.iter().map(|(first_slot, voters)| {
+            let missing: Vec<&Pubkey> = expected.iter().filter(|key| !voters.contains(key)).collect();
+            missing.iter().for_each(|key| *windows_missed.entry(**key).or_insert(0) += 1);
+            WindowVoters { first_slot: *first_slot, voters: voters.len(), missing: missing.iter().map(|k| k.to_string()).collect() }
+        }).collect();
+
+        let mut validators: Vec<ValidatorRow> = self.validators.iter().map(|(key, v)| {
+            let succeeded = v.votes - v.failed;
+            ValidatorRow {
+                vote_account: key.to_string(),
+                authorities: v.authorities.iter().map(|a| a.to_string()).collect(),
+                vote_txs: v.vote_txs,
+                votes: v.votes,
+                failed: v.failed,
+                slots_voted: v.slots_voted,
+                mean_latency: if succeeded == 0 { 0.0 } else { v.latency_sum as f64 / succeeded as f64 },
+                windows_missed: windows_missed.get(key).copied().unwrap_or(0),
+            }
+        }).collect();
+        ranked(&mut validators, |v| v.votes, |a, b| a.vote_account.cmp(&b.vote_account));
+
+        self.report = Some(VoteReport {
+            txs: self.txs,
+            vote_txs: self.vote_txs,
+            non_vote_txs: self.txs - self.vote_txs,
+            vote_share: if self.txs == 0 { 0.0 } else { self.vote_txs as f64 / self.txs as f64 },
+            latency_percentiles: percentiles(&self.latency),
+            latency: self.latency.iter().map(|(slots, votes)| LatencyCount { slots: *slots, votes: *votes }).collect(),
+            validators,
+            slot_window: self.slot_window,
+            windows,
+        });
+    }
+
+    fn print_report(&self) {
+        let report = match &self.report {
+            Some(r) => r,
+            None => return,
+        };
+        println!("transactions:  {},  votes:  {} ({:.2}%),  non-votes:  {}",
+                 report.txs, report.vote_txs, report.vote_share * 100.0, report.non_vote_txs);
+        if report.vote_txs == 0 { return }
+
+        println!("\n    vote latency (slots after the latest slot voted on)");
+        report.latency_percentiles.iter().for_each(|p| println!("    p{:<3} {:>12}", p.percentile, p.value));
+
+        println!("\nvalidators voting:  {}", report.validators.len());
+        println!("    {:<44} {:>10} {:>10} {:>10} {:>12} {:>14}",
+                 "vote account", "votes", "failed", "slots", "mean latency", "windows missed");
+        report.validators.iter().take(self.top).for_each(|v| {
+            println!("    {:<44} {:>10} {:>10} {:>10} {:>12.2} {:>14}",
+                     v.vote_account, v.votes, v.failed, v.slots_voted, v.mean_latency, v.windows_missed);
+        });
+
+        // every window is in the output file, only those with the most missing voters are printed
+        let mut worst: Vec<&WindowVoters> = report.windows.iter().filter(|w| !w.missing.is_empty()).collect();
+        ranked(&mut worst, |w| w.missing.len(), |a, b| a.first_slot.cmp(&b.first_slot));
+        println!("\n{} of {} windows of {} slots had missing voters", worst.len(), report.windows.len(), report.slot_window);
+        worst.iter().take(self.top).for_each(|w| {
+            println!("    {:<12} voters:  {:>6},  missing:  {:>6}  {}", w.first_slot, w.voters, w.missing.len(),
+                     w.missing.iter().take(3).cloned().collect::<Vec<_>>().join(", "));
+        });
+    }
+
+    fn write_output(&self, out: &AnalysisOutput) -> Result<(), String> {
+        match &self.report {
+            Some(report) => out.write_json("", report),
+            None => Ok(()),
+        }
+    }
+}